@@ -0,0 +1,389 @@
+use crate::resolve::{Outcome, Resolution};
+use anyhow::{Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Bump this when the format of cache file is changed. Cache files of other versions are ignored
+const CACHE_VERSION: u32 = 2;
+
+pub const DEFAULT_POSITIVE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+pub const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    #[serde(flatten)]
+    pub resolution: Resolution,
+    // Fingerprint of the options used to resolve the URL such as allowed statuses and request headers. The same URL can
+    // be resolved differently with other options so entries are looked up with both the URL and the fingerprint
+    pub policy: String,
+    // Unix time in seconds when the URL was resolved
+    pub cached_at: u64,
+}
+
+impl CacheEntry {
+    pub fn cached_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.cached_at)
+    }
+}
+
+#[derive(Deserialize)]
+struct CacheVersion {
+    version: u32,
+}
+
+// Entries of each URL. Each entry has its own policy
+type Entries = HashMap<String, Vec<CacheEntry>>;
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: Entries,
+}
+
+// Resolutions of URLs persisted in a JSON file across runs. Redirected and not redirected URLs are positive entries and
+// broken URLs are negative entries. Each of them has its own TTL
+pub struct DiskCache {
+    path: PathBuf,
+    positive_ttl: Duration,
+    negative_ttl: Duration,
+    entries: Mutex<Entries>,
+    dirty: AtomicBool,
+}
+
+impl DiskCache {
+    // $XDG_CACHE_HOME/fixred/cache.json or ~/.cache/fixred/cache.json
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => {
+                let mut dir = PathBuf::from(env::var_os("HOME")?);
+                dir.push(".cache");
+                dir
+            }
+        };
+        Some(dir.join("fixred").join("cache.json"))
+    }
+
+    // Load the cache file. When the file does not exist, the cache is empty
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let entries = match fs::read(&path) {
+            Ok(bytes) => {
+                let parse_error = || format!("Could not parse cache file {:?}", &path);
+                let v: CacheVersion = serde_json::from_slice(&bytes).with_context(parse_error)?;
+                if v.version == CACHE_VERSION {
+                    let file: CacheFile =
+                        serde_json::from_slice(&bytes).with_context(parse_error)?;
+                    file.entries
+                } else {
+                    debug!(
+                        "Ignored cache file {:?} since its version {} is not {}",
+                        &path, v.version, CACHE_VERSION
+                    );
+                    HashMap::new()
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Could not read cache file {:?}", &path))
+            }
+        };
+        debug!(
+            "Loaded entries of {} URLs from cache file {:?}",
+            entries.len(),
+            &path
+        );
+        Ok(Self {
+            path,
+            positive_ttl: DEFAULT_POSITIVE_TTL,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    pub fn ttl(mut self, positive: Duration, negative: Duration) -> Self {
+        self.positive_ttl = positive;
+        self.negative_ttl = negative;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_expired(&self, entry: &CacheEntry, now: SystemTime) -> bool {
+        let ttl = if entry.resolution.outcome.is_broken() {
+            self.negative_ttl
+        } else {
+            self.positive_ttl
+        };
+        unix_secs(now) >= entry.cached_at.saturating_add(ttl.as_secs())
+    }
+
+    pub fn get(&self, url: &str, policy: &str) -> Option<Resolution> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(url)?.iter().find(|e| e.policy == policy)?;
+        if self.is_expired(entry, SystemTime::now()) {
+            debug!("Cache entry for {} in cache file is expired", url);
+            return None;
+        }
+        Some(entry.resolution.clone())
+    }
+
+    // Skipped URLs and network errors are not stored since they are not results of resolving URLs
+    pub fn insert(&self, url: &str, policy: &str, resolution: &Resolution) {
+        if matches!(
+            resolution.outcome,
+            Outcome::Skipped | Outcome::NetworkError(..)
+        ) {
+            return;
+        }
+        let entry = CacheEntry {
+            resolution: resolution.clone(),
+            policy: policy.to_string(),
+            cached_at: unix_secs(SystemTime::now()),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        let for_url = entries.entry(url.to_string()).or_default();
+        for_url.retain(|e| e.policy != policy);
+        for_url.push(entry);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    // All entries sorted by URL. One URL may have multiple entries for different policies
+    pub fn entries(&self) -> Vec<(String, CacheEntry)> {
+        let entries = self.entries.lock().unwrap();
+        let mut v: Vec<_> = entries
+            .iter()
+            .flat_map(|(u, es)| es.iter().map(move |e| (u.clone(), e.clone())))
+            .collect();
+        v.sort_by(|a, b| (&a.0, &a.1.policy).cmp(&(&b.0, &b.1.policy)));
+        v
+    }
+
+    // Remove expired entries and return the number of removed entries
+    pub fn prune(&self) -> usize {
+        let now = SystemTime::now();
+        let mut entries = self.entries.lock().unwrap();
+        let mut removed = 0;
+        entries.retain(|_, es| {
+            let before = es.len();
+            es.retain(|e| !self.is_expired(e, now));
+            removed += before - es.len();
+            !es.is_empty()
+        });
+        if removed > 0 {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        removed
+    }
+
+    // Remove all entries and return the number of removed entries
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let removed = entries.values().map(Vec::len).sum();
+        entries.clear();
+        self.dirty.store(true, Ordering::Relaxed);
+        removed
+    }
+
+    // Write the entries to the cache file when they were modified
+    pub fn save(&self) -> Result<()> {
+        if !self.dirty.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let entries = self.entries.lock().unwrap();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create cache directory {:?}", dir))?;
+        }
+        let file = CacheFile {
+            version: CACHE_VERSION,
+            entries: entries.clone(),
+        };
+        // Write to a temporary file and rename it so that the cache file is not corrupted on failure
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(&file)?)
+            .with_context(|| format!("Could not write cache file {:?}", &tmp))?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Could not write cache file {:?}", &self.path))?;
+        debug!(
+            "Saved entries of {} URLs to cache file {:?}",
+            entries.len(),
+            &self.path
+        );
+        self.dirty.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::{Hop, NetworkErrorKind};
+    use crate::test_helper::TestDir;
+
+    fn redirected(url: &str) -> Resolution {
+        Resolution {
+            outcome: Outcome::Redirected(url.to_string()),
+            chain: vec![
+                Hop {
+                    url: "https://foo.example.com".to_string(),
+                    status: 301,
+                    location: Some(url.to_string()),
+                },
+                Hop {
+                    url: url.to_string(),
+                    status: 200,
+                    location: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn save_and_load_cache() {
+        let dir = TestDir::new(&[]).unwrap();
+        let path = dir.root.join("cache").join("cache.json");
+
+        let cache = DiskCache::open(&path).unwrap();
+        assert!(cache.entries().is_empty());
+        let r = redirected("https://bar.example.com");
+        cache.insert("https://foo.example.com", "p", &r);
+        cache.insert(
+            "https://notfound.example.com",
+            "p",
+            &Resolution::new(Outcome::HttpError(404)),
+        );
+        cache.insert(
+            "https://error.example.com",
+            "p",
+            &Resolution::new(Outcome::NetworkError(
+                NetworkErrorKind::Dns,
+                "oops".to_string(),
+            )),
+        );
+        cache.insert(
+            "https://skipped.example.com",
+            "p",
+            &Resolution::new(Outcome::Skipped),
+        );
+        cache.save().unwrap();
+
+        let cache = DiskCache::open(&path).unwrap();
+        let urls: Vec<_> = cache.entries().into_iter().map(|(u, _)| u).collect();
+        assert_eq!(
+            urls,
+            &["https://foo.example.com", "https://notfound.example.com"]
+        );
+        assert_eq!(cache.get("https://foo.example.com", "p"), Some(r));
+        assert_eq!(
+            cache
+                .get("https://notfound.example.com", "p")
+                .unwrap()
+                .outcome,
+            Outcome::HttpError(404)
+        );
+        assert_eq!(cache.get("https://error.example.com", "p"), None);
+    }
+
+    #[test]
+    fn separate_entries_by_policy() {
+        let dir = TestDir::new(&[]).unwrap();
+        let path = dir.root.join("cache.json");
+
+        let cache = DiskCache::open(&path).unwrap();
+        let url = "https://foo.example.com";
+        let r = redirected("https://bar.example.com");
+        cache.insert(url, "default", &r);
+        cache.insert(url, "shallow", &Resolution::new(Outcome::NotRedirected));
+        cache.insert(url, "shallow", &Resolution::new(Outcome::HttpError(404)));
+        cache.save().unwrap();
+
+        let cache = DiskCache::open(&path).unwrap();
+        assert_eq!(cache.entries().len(), 2);
+        assert_eq!(cache.get(url, "default"), Some(r));
+        assert_eq!(
+            cache.get(url, "shallow").unwrap().outcome,
+            Outcome::HttpError(404)
+        );
+        assert_eq!(cache.get(url, "other"), None);
+        assert_eq!(cache.clear(), 2);
+    }
+
+    #[test]
+    fn expire_entries_by_ttl() {
+        let dir = TestDir::new(&[]).unwrap();
+        let cache = DiskCache::open(dir.root.join("cache.json"))
+            .unwrap()
+            .ttl(Duration::from_secs(100), Duration::from_secs(10));
+
+        let now = unix_secs(SystemTime::now());
+        let mut entries = cache.entries.lock().unwrap();
+        for (url, outcome, age) in [
+            ("https://fresh.example.com", Outcome::NotRedirected, 50),
+            ("https://old.example.com", Outcome::NotRedirected, 150),
+            ("https://fresh-404.example.com", Outcome::HttpError(404), 5),
+            ("https://old-404.example.com", Outcome::HttpError(404), 50),
+        ] {
+            let entry = CacheEntry {
+                resolution: Resolution::new(outcome),
+                policy: "p".to_string(),
+                cached_at: now - age,
+            };
+            entries.insert(url.to_string(), vec![entry]);
+        }
+        drop(entries);
+
+        assert!(cache.get("https://fresh.example.com", "p").is_some());
+        assert!(cache.get("https://old.example.com", "p").is_none());
+        assert!(cache.get("https://fresh-404.example.com", "p").is_some());
+        assert!(cache.get("https://old-404.example.com", "p").is_none());
+
+        assert_eq!(cache.prune(), 2);
+        let urls: Vec<_> = cache.entries().into_iter().map(|(u, _)| u).collect();
+        assert_eq!(
+            urls,
+            &["https://fresh-404.example.com", "https://fresh.example.com"]
+        );
+
+        assert_eq!(cache.clear(), 2);
+        assert!(cache.entries().is_empty());
+    }
+
+    #[test]
+    fn ignore_cache_of_other_version() {
+        let dir = TestDir::new(&[]).unwrap();
+        let path = dir.root.join("cache.json");
+        fs::write(&path, r#"{"version":0,"entries":{"https://foo":{}}}"#).unwrap();
+        let cache = DiskCache::open(&path).unwrap();
+        assert!(cache.entries().is_empty());
+
+        fs::write(&path, "oops").unwrap();
+        assert!(DiskCache::open(&path).is_err());
+    }
+
+    #[test]
+    fn do_not_save_unmodified_cache() {
+        let dir = TestDir::new(&[]).unwrap();
+        let path = dir.root.join("cache.json");
+        let cache = DiskCache::open(&path).unwrap();
+        cache.save().unwrap();
+        assert!(!path.exists());
+    }
+}
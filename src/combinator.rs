@@ -0,0 +1,334 @@
+use crate::resolve::{Outcome, Resolution, Resolver};
+use chashmap::CHashMap;
+use log::{debug, info};
+
+// Resolver which tries the first resolver and falls back to the second resolver when the first one did not redirect
+// the URL. For example, Chain<RuleResolver, CurlResolver> rewrites URLs with rules and sends requests only for URLs
+// which no rule matched
+#[derive(Default)]
+pub struct Chain<A: Resolver, B: Resolver> {
+    first: A,
+    second: B,
+}
+
+impl<A: Resolver, B: Resolver> Chain<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<A: Resolver, B: Resolver> Resolver for Chain<A, B> {
+    fn shallow(&mut self, enabled: bool) {
+        self.first.shallow(enabled);
+        self.second.shallow(enabled);
+    }
+
+    fn resolve(&self, url: &str) -> Resolution {
+        let res = self.first.resolve(url);
+        if res.redirect().is_some() {
+            return res;
+        }
+        self.second.resolve(url)
+    }
+}
+
+// Resolver which falls back to the second resolver only when the first resolver failed to resolve the URL due to HTTP
+// errors or network errors
+#[derive(Default)]
+pub struct Fallback<A: Resolver, B: Resolver> {
+    primary: A,
+    fallback: B,
+}
+
+impl<A: Resolver, B: Resolver> Fallback<A, B> {
+    pub fn new(primary: A, fallback: B) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+impl<A: Resolver, B: Resolver> Resolver for Fallback<A, B> {
+    fn shallow(&mut self, enabled: bool) {
+        self.primary.shallow(enabled);
+        self.fallback.shallow(enabled);
+    }
+
+    fn resolve(&self, url: &str) -> Resolution {
+        let res = self.primary.resolve(url);
+        if !res.outcome.is_broken() {
+            return res;
+        }
+        debug!(
+            "Falling back since {} was not resolved: {}",
+            url, res.outcome
+        );
+        self.fallback.resolve(url)
+    }
+}
+
+// Resolver which memoizes resolutions of the inner resolver. Network errors are not cached since they may be temporary
+pub struct Cached<R: Resolver> {
+    inner: R,
+    cache: CHashMap<String, Resolution>,
+}
+
+impl<R: Resolver> Cached<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            cache: CHashMap::new(),
+        }
+    }
+}
+
+impl<R: Resolver + Default> Default for Cached<R> {
+    fn default() -> Self {
+        Self::new(R::default())
+    }
+}
+
+impl<R: Resolver> Resolver for Cached<R> {
+    fn shallow(&mut self, enabled: bool) {
+        self.inner.shallow(enabled);
+        // Cached resolutions are no longer valid
+        self.cache.clear();
+    }
+
+    fn resolve(&self, url: &str) -> Resolution {
+        if let Some(r) = self.cache.get(url) {
+            return r.clone();
+        }
+        let res = self.inner.resolve(url);
+        if !matches!(res.outcome, Outcome::NetworkError(..)) {
+            self.cache.insert(url.to_string(), res.clone());
+        }
+        res
+    }
+}
+
+// Resolver which resolves only URLs accepted by the predicate. Other URLs are skipped
+pub struct Filtered<R: Resolver, F: Fn(&str) -> bool + Sync> {
+    inner: R,
+    pred: F,
+}
+
+impl<R: Resolver, F: Fn(&str) -> bool + Sync> Filtered<R, F> {
+    pub fn new(inner: R, pred: F) -> Self {
+        Self { inner, pred }
+    }
+}
+
+impl<R: Resolver, F: Fn(&str) -> bool + Sync> Resolver for Filtered<R, F> {
+    fn shallow(&mut self, enabled: bool) {
+        self.inner.shallow(enabled);
+    }
+
+    fn resolve(&self, url: &str) -> Resolution {
+        if (self.pred)(url) {
+            self.inner.resolve(url)
+        } else {
+            debug!("Skipped URL by filter: {}", url);
+            Resolution::new(Outcome::Skipped)
+        }
+    }
+}
+
+// Resolver which logs resolutions of the inner resolver with the name
+pub struct Logged<R: Resolver> {
+    name: String,
+    inner: R,
+}
+
+impl<R: Resolver> Logged<R> {
+    pub fn new(name: impl Into<String>, inner: R) -> Self {
+        Self {
+            name: name.into(),
+            inner,
+        }
+    }
+}
+
+impl<R: Resolver> Resolver for Logged<R> {
+    fn shallow(&mut self, enabled: bool) {
+        self.inner.shallow(enabled);
+    }
+
+    fn resolve(&self, url: &str) -> Resolution {
+        let res = self.inner.resolve(url);
+        info!("{}: {} is {}", self.name, url, res.outcome);
+        res
+    }
+}
+
+// Methods to compose resolvers. For example, rules.chain(mapping).chain(curl).cached() builds a resolver which tries
+// rules, the mapping and sending requests in order and caches the results
+pub trait ResolverExt: Resolver + Sized {
+    fn chain<R: Resolver>(self, next: R) -> Chain<Self, R> {
+        Chain::new(self, next)
+    }
+
+    fn fallback<R: Resolver>(self, fallback: R) -> Fallback<Self, R> {
+        Fallback::new(self, fallback)
+    }
+
+    fn cached(self) -> Cached<Self> {
+        Cached::new(self)
+    }
+
+    fn filter<F: Fn(&str) -> bool + Sync>(self, pred: F) -> Filtered<Self, F> {
+        Filtered::new(self, pred)
+    }
+
+    fn logged(self, name: impl Into<String>) -> Logged<Self> {
+        Logged::new(name, self)
+    }
+}
+
+impl<R: Resolver> ResolverExt for R {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "mapping")]
+    use crate::mapping::MappingResolver;
+    use crate::resolve::NetworkErrorKind;
+    use crate::rule::{Rule, RuleResolver};
+    use crate::test_helper::FooToPiyoResolver;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn chain_resolvers() {
+        let rules = RuleResolver::new(vec![Rule::new("^https://foo\\.", "https://rule.").unwrap()]);
+        let mut res = Chain::new(rules, FooToPiyoResolver::default());
+
+        let tests = &[
+            (
+                "https://foo.example.com",
+                Outcome::Redirected("https://rule.example.com".to_string()),
+            ),
+            (
+                "https://example.com/foo",
+                Outcome::Redirected("https://example.com/piyo".to_string()),
+            ),
+            ("https://example.com", Outcome::NotRedirected),
+        ];
+        for (url, want) in tests {
+            assert_eq!(&res.resolve(url).outcome, want, "{:?}", url);
+        }
+
+        res.shallow(true);
+        assert!(res.second.shallow);
+        assert_eq!(
+            res.resolve("https://example.com/foo").redirect(),
+            Some("https://example.com/bar")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "mapping")]
+    fn fallback_on_error() {
+        let mapping: MappingResolver = vec![
+            ("https://error.example.com", "https://ok.example.com"),
+            ("https://foo.example.com", "https://mapped.example.com"),
+        ]
+        .into_iter()
+        .collect();
+        let res = FooToPiyoResolver::default().fallback(mapping);
+
+        let tests = &[
+            (
+                "https://error.example.com",
+                Outcome::Redirected("https://ok.example.com".to_string()),
+            ),
+            (
+                "https://foo.example.com",
+                Outcome::Redirected("https://piyo.example.com".to_string()),
+            ),
+            ("https://notfound.example.com", Outcome::NotRedirected),
+        ];
+        for (url, want) in tests {
+            assert_eq!(&res.resolve(url).outcome, want, "{:?}", url);
+        }
+    }
+
+    // Counts how many times URLs are resolved
+    #[derive(Default)]
+    struct CountingResolver {
+        count: AtomicUsize,
+    }
+
+    impl Resolver for CountingResolver {
+        fn shallow(&mut self, _enabled: bool) {}
+        fn resolve(&self, url: &str) -> Resolution {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            if url.contains("error") {
+                let outcome = Outcome::NetworkError(NetworkErrorKind::Other, "oops".to_string());
+                return Resolution::new(outcome);
+            }
+            Resolution::new(Outcome::NotRedirected)
+        }
+    }
+
+    #[test]
+    fn cache_resolutions() {
+        let mut res = CountingResolver::default().cached();
+        for _ in 0..3 {
+            res.resolve("https://example.com");
+            res.resolve("https://error.example.com");
+        }
+        assert_eq!(res.inner.count.load(Ordering::Relaxed), 4);
+        assert!(res.cache.get("https://error.example.com").is_none());
+
+        res.shallow(true);
+        res.resolve("https://example.com");
+        assert_eq!(res.inner.count.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn filter_urls() {
+        let res = FooToPiyoResolver::default().filter(|u| !u.contains("github.com"));
+        assert_eq!(
+            res.resolve("https://foo.github.com").outcome,
+            Outcome::Skipped
+        );
+        assert_eq!(
+            res.resolve("https://foo.example.com").redirect(),
+            Some("https://piyo.example.com")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "mapping")]
+    fn compose_resolvers() {
+        let rules = RuleResolver::new(vec![Rule::new("^http://", "https://").unwrap()]);
+        let mapping: MappingResolver = vec![("https://a.example.com", "https://b.example.com")]
+            .into_iter()
+            .collect();
+        // Cache(Chain(Rules, Mapping, Resolver)) without Default
+        let res = rules
+            .chain(mapping)
+            .chain(FooToPiyoResolver::default())
+            .filter(|u| !u.ends_with(".org"))
+            .logged("test")
+            .cached();
+
+        let tests = &[
+            (
+                "http://example.com",
+                Outcome::Redirected("https://example.com".to_string()),
+            ),
+            (
+                "https://a.example.com",
+                Outcome::Redirected("https://b.example.com".to_string()),
+            ),
+            (
+                "https://foo.example.com",
+                Outcome::Redirected("https://piyo.example.com".to_string()),
+            ),
+            ("https://example.org", Outcome::Skipped),
+            ("https://example.com", Outcome::NotRedirected),
+        ];
+        for (url, want) in tests {
+            assert_eq!(&res.resolve(url).outcome, want, "{:?}", url);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::header::{HostPattern, RequestHeaders};
+use crate::rule::{Rule, RuleResolver};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    pattern: String,
+    replace: String,
+}
+
+// Secrets are not written in the config file directly. They are read from environment variables
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct HostConfig {
+    pattern: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    // Header name to environment variable name
+    #[serde(default)]
+    headers_env: BTreeMap<String, String>,
+    // Environment variable name of token sent as "Authorization: Bearer {token}"
+    bearer_token_env: Option<String>,
+}
+
+impl HostConfig {
+    fn headers(&self) -> Result<Vec<(String, String)>> {
+        let var = |name: &str| {
+            env::var(name).with_context(|| {
+                format!(
+                    "Could not read environment variable ${} for host {:?}",
+                    name, self.pattern
+                )
+            })
+        };
+        let mut headers: Vec<_> = self
+            .headers
+            .iter()
+            .map(|(n, v)| (n.clone(), v.clone()))
+            .collect();
+        for (name, env) in self.headers_env.iter() {
+            headers.push((name.clone(), var(env)?));
+        }
+        if let Some(env) = &self.bearer_token_env {
+            headers.push(("Authorization".to_string(), format!("Bearer {}", var(env)?)));
+        }
+        Ok(headers)
+    }
+}
+
+// Configuration file in TOML
+//
+// ```toml
+// [[rules]]
+// pattern = '^https://travis-ci\.org/(.+)$'
+// replace = 'https://app.travis-ci.com/$1'
+//
+// [[hosts]]
+// pattern = '*.github.example.com'
+// bearer_token_env = 'GHE_TOKEN'
+// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+    #[serde(default)]
+    hosts: Vec<HostConfig>,
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {:?}", path))?;
+        Self::parse(&content).with_context(|| format!("Could not parse config file {:?}", path))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn rule_resolver(&self) -> Result<RuleResolver> {
+        let rules = self
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(i, r)| {
+                Rule::new(&r.pattern, &r.replace)
+                    .with_context(|| format!("Invalid rule at index {} in config", i))
+            })
+            .collect::<Result<_>>()?;
+        Ok(RuleResolver::new(rules))
+    }
+
+    // Add headers for hosts in [[hosts]] sections. Environment variables are read here
+    pub fn request_headers(&self, mut headers: RequestHeaders) -> Result<RequestHeaders> {
+        for (i, host) in self.hosts.iter().enumerate() {
+            let h = host
+                .headers()
+                .with_context(|| format!("Invalid host at index {} in config", i))?;
+            headers = headers.host(HostPattern::new(&host.pattern), h);
+        }
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::Resolver;
+    use crate::test_helper::{TestDir, TestDirEntry};
+
+    #[test]
+    fn load_rules_from_config() {
+        let dir = TestDir::new(&[TestDirEntry::File(
+            "fixred.toml",
+            r#"
+[[rules]]
+pattern = '^http://(www\.)?example\.com/'
+replace = 'https://example.com/'
+
+[[rules]]
+pattern = '^https://example\.com/docs/(.+)$'
+replace = 'https://docs.example.com/$1'
+"#,
+        )])
+        .unwrap();
+        let config = Config::from_file(&dir.root.join("fixred.toml")).unwrap();
+        let res = config.rule_resolver().unwrap();
+        assert_eq!(
+            res.resolve("http://www.example.com/docs/intro").redirect(),
+            Some("https://docs.example.com/intro")
+        );
+
+        assert!(Config::parse("")
+            .unwrap()
+            .rule_resolver()
+            .unwrap()
+            .is_empty());
+        assert!(Config::from_file(&dir.root.join("missing.toml")).is_err());
+    }
+
+    #[test]
+    fn invalid_config() {
+        for input in &[
+            "[[rules]]\npattern = 'foo'\n",
+            "[[rules]]\npattern = 'foo'\nreplace = 'bar'\nunknown = 1\n",
+            "unknown = 1\n",
+            "rules = 'oops'\n",
+            "[[hosts]]\nheaders = { X-Foo = 'bar' }\n",
+            "[[hosts]]\npattern = 'example.com'\ntoken = 'xxx'\n",
+        ] {
+            assert!(Config::parse(input).is_err(), "{:?}", input);
+        }
+
+        let config = Config::parse("[[rules]]\npattern = '(foo'\nreplace = 'bar'\n").unwrap();
+        let err = config.rule_resolver().unwrap_err();
+        assert!(
+            format!("{:?}", err).contains("Invalid rule at index 0"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn load_host_headers_from_config() {
+        env::set_var("FIXRED_TEST_CONFIG_TOKEN", "secret");
+        env::set_var("FIXRED_TEST_CONFIG_PRIVATE_TOKEN", "private");
+        let config = Config::parse(
+            r#"
+[[hosts]]
+pattern = '*.example.com'
+bearer_token_env = 'FIXRED_TEST_CONFIG_TOKEN'
+
+[[hosts]]
+pattern = 'gitlab.example.com'
+headers = { Accept = 'text/html' }
+headers_env = { PRIVATE-TOKEN = 'FIXRED_TEST_CONFIG_PRIVATE_TOKEN' }
+"#,
+        )
+        .unwrap();
+        let headers = config.request_headers(RequestHeaders::default()).unwrap();
+        assert_eq!(
+            headers.for_url("https://git.example.com/foo"),
+            &[("Authorization", "Bearer secret")]
+        );
+        assert_eq!(
+            headers.for_url("https://gitlab.example.com/foo"),
+            &[
+                ("Authorization", "Bearer secret"),
+                ("Accept", "text/html"),
+                ("PRIVATE-TOKEN", "private")
+            ]
+        );
+        assert!(headers.for_url("https://github.com/foo").is_empty());
+
+        let config = Config::parse(
+            "[[hosts]]\npattern = 'example.com'\nbearer_token_env = 'FIXRED_TEST_CONFIG_UNDEFINED'\n",
+        )
+        .unwrap();
+        // RequestHeaders does not implement Debug not to leak tokens
+        let err = config
+            .request_headers(RequestHeaders::default())
+            .err()
+            .unwrap();
+        let msg = format!("{:?}", err);
+        assert!(msg.contains("Invalid host at index 0"), "{}", msg);
+        assert!(msg.contains("$FIXRED_TEST_CONFIG_UNDEFINED"), "{}", msg);
+    }
+}
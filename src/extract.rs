@@ -0,0 +1,494 @@
+use crate::html::{self, find_html_urls};
+use crate::markdown::{find_markdown_urls, MarkdownMode};
+use crate::source::{find_source_urls, Language, SourceMode};
+use crate::url::{find_all_urls, UrlContext};
+use anyhow::{Context, Result};
+use log::debug;
+use regex::Regex;
+use std::path::Path;
+
+// Span of a URL found in content. The text at start..end is the URL encoded for the context
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub context: UrlContext,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, context: UrlContext) -> Self {
+        Self {
+            start,
+            end,
+            context,
+        }
+    }
+
+    fn from_ranges(ranges: Vec<(usize, usize)>, context: UrlContext) -> Vec<Self> {
+        ranges
+            .into_iter()
+            .map(|(s, e)| Self::new(s, e, context))
+            .collect()
+    }
+}
+
+// Extractor finds URLs in content of a file. The path is None when the content is not read from a file such as stdin.
+// Spans should be sorted by their positions and should not overlap. Extractors sorts spans and drops overlapping or
+// invalid spans so that a misbehaving extractor does not break replacing URLs
+pub trait Extractor: Send + Sync {
+    fn extract(&self, content: &str, path: Option<&Path>) -> Vec<Span>;
+}
+
+// Extractor which finds all URLs in the content as plain text. This is used for files in unknown formats
+#[derive(Default, Debug)]
+pub struct TextExtractor;
+
+impl Extractor for TextExtractor {
+    fn extract(&self, content: &str, _path: Option<&Path>) -> Vec<Span> {
+        Span::from_ranges(find_all_urls(content), UrlContext::Text)
+    }
+}
+
+// Extractor for Markdown documents. See find_markdown_urls for details
+#[derive(Default, Debug)]
+pub struct MarkdownExtractor {
+    include_code: bool,
+}
+
+impl MarkdownExtractor {
+    pub fn new(include_code: bool) -> Self {
+        Self { include_code }
+    }
+}
+
+impl Extractor for MarkdownExtractor {
+    fn extract(&self, content: &str, _path: Option<&Path>) -> Vec<Span> {
+        let ranges = find_markdown_urls(content, self.include_code);
+        Span::from_ranges(ranges, UrlContext::Text)
+    }
+}
+
+// Extractor for HTML documents. See find_html_urls for details
+#[derive(Default, Debug)]
+pub struct HtmlExtractor;
+
+impl Extractor for HtmlExtractor {
+    fn extract(&self, content: &str, _path: Option<&Path>) -> Vec<Span> {
+        find_html_urls(content)
+    }
+}
+
+// Extractor for source code. The language is detected from the file path and only comments (and string literals when
+// include_strings is true) are searched. Files in unknown languages are searched as plain text
+#[derive(Default, Debug)]
+pub struct SourceExtractor {
+    include_strings: bool,
+}
+
+impl SourceExtractor {
+    pub fn new(include_strings: bool) -> Self {
+        Self { include_strings }
+    }
+}
+
+impl Extractor for SourceExtractor {
+    fn extract(&self, content: &str, path: Option<&Path>) -> Vec<Span> {
+        let ranges = match path.and_then(Language::from_path) {
+            Some(lang) => find_source_urls(content, lang, self.include_strings),
+            None => find_all_urls(content),
+        };
+        Span::from_ranges(ranges, UrlContext::Text)
+    }
+}
+
+// Translate glob pattern into regular expression. '*' and '?' do not match '/', "**" matches any number of directories
+// and "{a,b}" matches one of the alternatives
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::new();
+    let mut chars = glob.chars().peekable();
+    let mut in_brace = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    re.push_str("(?:.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '{' if !in_brace => {
+                in_brace = true;
+                re.push_str("(?:");
+            }
+            '}' if in_brace => {
+                in_brace = false;
+                re.push(')');
+            }
+            ',' if in_brace => re.push('|'),
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re
+}
+
+// Pattern of file paths to choose an extractor
+#[derive(Debug)]
+pub enum FilePattern {
+    // Extension without '.' such as "md". It is matched case-insensitively
+    Extension(String),
+    // Glob pattern like "docs/**/*.txt". Patterns without '/' are matched to file names and other patterns are matched
+    // to the ends of paths
+    Glob { regex: Regex, file_name: bool },
+}
+
+impl FilePattern {
+    pub fn extension(ext: &str) -> Self {
+        Self::Extension(ext.trim_start_matches('.').to_string())
+    }
+
+    pub fn glob(pattern: &str) -> Result<Self> {
+        let file_name = !pattern.contains('/');
+        let re = if file_name {
+            format!("^{}$", glob_to_regex(pattern))
+        } else {
+            format!(
+                "(?:^|/){}$",
+                glob_to_regex(pattern.trim_start_matches("./"))
+            )
+        };
+        let regex =
+            Regex::new(&re).with_context(|| format!("Invalid glob pattern {:?}", pattern))?;
+        Ok(Self::Glob { regex, file_name })
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        match self {
+            Self::Extension(ext) => path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.eq_ignore_ascii_case(ext))
+                .unwrap_or(false),
+            Self::Glob {
+                regex,
+                file_name: true,
+            } => path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| regex.is_match(n))
+                .unwrap_or(false),
+            Self::Glob { regex, .. } => {
+                let path = path.to_string_lossy();
+                if cfg!(windows) {
+                    regex.is_match(&path.replace('\\', "/"))
+                } else {
+                    regex.is_match(&path)
+                }
+            }
+        }
+    }
+}
+
+// Registry of extractors chosen by file paths. Extractors registered later take precedence. Markdown files are chosen
+// by MarkdownMode unless other extractors are registered for them. Files matched to no pattern are handled by the
+// default extractor. By default HTML files are registered with HtmlExtractor and the default extractor is TextExtractor.
+// Source files are handled by SourceExtractor unless SourceMode::Text is set
+pub struct Extractors {
+    rules: Vec<(FilePattern, Box<dyn Extractor>)>,
+    markdown_mode: MarkdownMode,
+    markdown: MarkdownExtractor,
+    // None when source files are searched as plain text
+    source: Option<SourceExtractor>,
+    default: Box<dyn Extractor>,
+}
+
+impl Default for Extractors {
+    fn default() -> Self {
+        let rules = html::EXTENSIONS
+            .iter()
+            .map(|ext| {
+                let e: Box<dyn Extractor> = Box::new(HtmlExtractor);
+                (FilePattern::extension(ext), e)
+            })
+            .collect();
+        Self {
+            rules,
+            markdown_mode: MarkdownMode::default(),
+            markdown: MarkdownExtractor::default(),
+            source: None,
+            default: Box::new(TextExtractor),
+        }
+    }
+}
+
+impl Extractors {
+    // Use the extractor for files matched to the pattern
+    pub fn register(mut self, pattern: FilePattern, extractor: impl Extractor + 'static) -> Self {
+        self.rules.push((pattern, Box::new(extractor)));
+        self
+    }
+
+    // Use the extractor for files matched to no pattern and stdin
+    pub fn default_extractor(mut self, extractor: impl Extractor + 'static) -> Self {
+        self.default = Box::new(extractor);
+        self
+    }
+
+    // Set which files are parsed as Markdown. Even MarkdownMode::Always does not win over registered extractors and
+    // source files, so it parses stdin and files handled by the default extractor
+    pub fn markdown(mut self, mode: MarkdownMode) -> Self {
+        self.markdown_mode = mode;
+        self
+    }
+
+    // Find URLs in code spans and code blocks of Markdown files
+    pub fn markdown_code(mut self, enabled: bool) -> Self {
+        self.markdown = MarkdownExtractor::new(enabled);
+        self
+    }
+
+    // Set which parts of source files such as .rs and .go are searched. By default whole files are searched
+    pub fn source(mut self, mode: SourceMode) -> Self {
+        self.source = match mode {
+            SourceMode::Text => None,
+            SourceMode::Comments => Some(SourceExtractor::new(false)),
+            SourceMode::CommentsAndStrings => Some(SourceExtractor::new(true)),
+        };
+        self
+    }
+
+    // Choose the extractor for the file path. None means stdin
+    pub fn find(&self, path: Option<&Path>) -> &dyn Extractor {
+        if let Some(path) = path {
+            if let Some((_, e)) = self.rules.iter().rev().find(|(p, _)| p.matches(path)) {
+                return e.as_ref();
+            }
+            if let Some(e) = &self.source {
+                if Language::from_path(path).is_some() {
+                    return e;
+                }
+            }
+        }
+        if self.markdown_mode.is_enabled(path) {
+            return &self.markdown;
+        }
+        self.default.as_ref()
+    }
+
+    // Spans are sorted and spans which are empty, out of the content, not at char boundaries or overlapping with
+    // preceding spans are dropped
+    pub fn extract(&self, content: &str, path: Option<&Path>) -> Vec<Span> {
+        let mut spans = self.find(path).extract(content, path);
+        spans.sort_by_key(|s| (s.start, s.end));
+        let mut prev_end = 0;
+        spans.retain(|s| {
+            let valid = prev_end <= s.start
+                && s.start < s.end
+                && content.is_char_boundary(s.start)
+                && content.is_char_boundary(s.end);
+            if valid {
+                prev_end = s.end;
+            } else {
+                debug!(
+                    "Invalid span {}..{} of URL in {:?} was dropped",
+                    s.start, s.end, path
+                );
+            }
+            valid
+        });
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_file_pattern() {
+        let tests = &[
+            (FilePattern::extension("md"), "README.md", true),
+            (FilePattern::extension(".md"), "docs/README.MD", true),
+            (FilePattern::extension("md"), "README.txt", false),
+            (FilePattern::extension("md"), "md", false),
+            (FilePattern::glob("*.txt").unwrap(), "a/b/c.txt", true),
+            (FilePattern::glob("*.txt").unwrap(), "a.txt.bak", false),
+            (
+                FilePattern::glob("CHANGELOG*").unwrap(),
+                "CHANGELOG.md",
+                true,
+            ),
+            (FilePattern::glob("?.rst").unwrap(), "a.rst", true),
+            (FilePattern::glob("?.rst").unwrap(), "ab.rst", false),
+            (
+                FilePattern::glob("*.{adoc,asciidoc}").unwrap(),
+                "x.asciidoc",
+                true,
+            ),
+            (
+                FilePattern::glob("*.{adoc,asciidoc}").unwrap(),
+                "x.doc",
+                false,
+            ),
+            (
+                FilePattern::glob("docs/*.txt").unwrap(),
+                "repo/docs/a.txt",
+                true,
+            ),
+            (
+                FilePattern::glob("docs/*.txt").unwrap(),
+                "repo/docs/a/b.txt",
+                false,
+            ),
+            (
+                FilePattern::glob("./docs/**/*.txt").unwrap(),
+                "docs/a/b.txt",
+                true,
+            ),
+            (
+                FilePattern::glob("docs/**/*.txt").unwrap(),
+                "docs/a.txt",
+                true,
+            ),
+            (
+                FilePattern::glob("docs/**/*.txt").unwrap(),
+                "mydocs/a.txt",
+                false,
+            ),
+            (FilePattern::glob("a+b.txt").unwrap(), "a+b.txt", true),
+        ];
+        for (pattern, path, want) in tests {
+            assert_eq!(
+                pattern.matches(Path::new(path)),
+                *want,
+                "{:?} {:?}",
+                pattern,
+                path
+            );
+        }
+    }
+
+    // Extractor which finds words starting with "url:" for testing
+    struct PrefixExtractor;
+
+    impl Extractor for PrefixExtractor {
+        fn extract(&self, content: &str, _path: Option<&Path>) -> Vec<Span> {
+            content
+                .match_indices("url:")
+                .map(|(i, _)| {
+                    let start = i + "url:".len();
+                    let len = content[start..].find(' ').unwrap_or(content.len() - start);
+                    Span::new(start, start + len, UrlContext::Text)
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn choose_extractor_by_path() {
+        let content = "[a](https://example.com/a) `https://example.com/b` <a href=\"https://example.com/c\"> url:https://example.com/d";
+        let urls = |extractors: &Extractors, path: Option<&str>| -> Vec<String> {
+            extractors
+                .extract(content, path.map(Path::new))
+                .into_iter()
+                .map(|s| content[s.start..s.end].to_string())
+                .collect()
+        };
+        let all = &[
+            "https://example.com/a",
+            "https://example.com/b",
+            "https://example.com/c",
+            "https://example.com/d",
+        ];
+
+        let e = Extractors::default();
+        assert_eq!(urls(&e, Some("a.txt")), all);
+        assert_eq!(urls(&e, None), all);
+        assert_eq!(
+            urls(&e, Some("a.md")),
+            &[
+                "https://example.com/a",
+                "https://example.com/c",
+                "https://example.com/d"
+            ]
+        );
+        assert_eq!(urls(&e, Some("a.html")), all);
+        let html = e.extract(content, Some(Path::new("a.html")));
+        assert_eq!(html[2].context, UrlContext::Html);
+
+        let e = Extractors::default()
+            .register(FilePattern::extension("conf"), PrefixExtractor)
+            .register(FilePattern::glob("special/*.md").unwrap(), PrefixExtractor);
+        assert_eq!(urls(&e, Some("a.conf")), &["https://example.com/d"]);
+        assert_eq!(urls(&e, Some("special/a.md")), &["https://example.com/d"]);
+        assert_eq!(urls(&e, Some("a.md")).len(), 3);
+
+        let e = Extractors::default().markdown_code(true);
+        assert_eq!(urls(&e, Some("a.md")).len(), 4);
+
+        let e = Extractors::default()
+            .register(FilePattern::extension("conf"), PrefixExtractor)
+            .markdown(MarkdownMode::Always);
+        assert_eq!(urls(&e, None).len(), 3);
+        assert_eq!(urls(&e, Some("a.txt")).len(), 3);
+        assert_eq!(urls(&e, Some("a.conf")), &["https://example.com/d"]);
+        assert_eq!(urls(&e, Some("a.html")), all);
+        let e = e.source(SourceMode::Comments);
+        let src = "// [a](https://example.com/a) `https://example.com/b`\n";
+        assert_eq!(e.extract(src, Some(Path::new("main.rs"))).len(), 2);
+
+        let e = Extractors::default().source(SourceMode::Comments);
+        let src = "// https://example.com/a\nlet s = \"https://example.com/b\";\n";
+        let spans = e.extract(src, Some(Path::new("main.rs")));
+        assert_eq!(spans, &[Span::new(3, 24, UrlContext::Text)]);
+        assert_eq!(e.extract(src, Some(Path::new("a.txt"))).len(), 2);
+        let e = e.source(SourceMode::CommentsAndStrings);
+        assert_eq!(e.extract(src, Some(Path::new("main.rs"))).len(), 2);
+
+        let e = Extractors::default()
+            .markdown(MarkdownMode::Never)
+            .default_extractor(PrefixExtractor);
+        assert_eq!(urls(&e, Some("a.md")), &["https://example.com/d"]);
+        assert_eq!(urls(&e, Some("a.html")), all);
+    }
+
+    // Extractor which returns unsorted, overlapping and invalid spans
+    struct BrokenExtractor;
+
+    impl Extractor for BrokenExtractor {
+        fn extract(&self, content: &str, _path: Option<&Path>) -> Vec<Span> {
+            [
+                (10, 14),
+                (0, 5),
+                (3, 8),
+                (6, 6),
+                (8, 11),
+                (15, 16),
+                (12, 100),
+            ]
+            .iter()
+            .map(|&(s, e)| Span::new(s, e, UrlContext::Text))
+            .chain(std::iter::once(Span::new(
+                content.len(),
+                content.len() + 1,
+                UrlContext::Text,
+            )))
+            .collect()
+        }
+    }
+
+    #[test]
+    fn drop_invalid_spans() {
+        let content = "aaaaaaaaaaaaaaaあ";
+        let e = Extractors::default().default_extractor(BrokenExtractor);
+        let spans: Vec<_> = e
+            .extract(content, None)
+            .into_iter()
+            .map(|s| (s.start, s.end))
+            .collect();
+        assert_eq!(spans, &[(0, 5), (8, 11)]);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::url::find_host;
+use anyhow::{Context, Result};
+
+pub const DEFAULT_USER_AGENT: &str = concat!("fixred/", env!("CARGO_PKG_VERSION"));
+
+// Parse "Name: value" header line like curl's -H option
+pub fn parse_header(line: &str) -> Result<(String, String)> {
+    let (name, value) = line.split_once(':').with_context(|| {
+        format!(
+            "Header must be in \"Name: value\" format but got {:?}",
+            line
+        )
+    })?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(|c: char| c.is_ascii_whitespace() || c.is_ascii_control()) {
+        anyhow::bail!("Invalid header name {:?} in {:?}", name, line);
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+// Pattern of host names. "example.com" matches the host exactly and "*.example.com" matches all its subdomains
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct HostPattern(String);
+
+impl HostPattern {
+    pub fn new(pattern: &str) -> Self {
+        Self(pattern.to_ascii_lowercase())
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        match self.0.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .map(|h| h.len() > 1 && h.ends_with('.'))
+                .unwrap_or(false),
+            None => host == self.0,
+        }
+    }
+}
+
+// Headers sent with each request. Headers for hosts are chosen by the host of each URL in a redirect chain so that
+// tokens for one host are never sent to other hosts after redirects
+#[derive(Clone, Default, Hash)]
+pub struct RequestHeaders {
+    headers: Vec<(String, String)>,
+    hosts: Vec<(HostPattern, Vec<(String, String)>)>,
+}
+
+impl RequestHeaders {
+    // Add header sent to all hosts
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    // Add headers sent only to hosts matched to the pattern
+    pub fn host(mut self, pattern: HostPattern, headers: Vec<(String, String)>) -> Self {
+        self.hosts.push((pattern, headers));
+        self
+    }
+
+    // Headers for the URL. Headers for the host override headers for all hosts with the same name
+    pub fn for_url(&self, url: &str) -> Vec<(&str, &str)> {
+        let mut ret: Vec<(&str, &str)> = vec![];
+        let host = find_host(url);
+        let for_host = self
+            .hosts
+            .iter()
+            .filter(|(p, _)| host.map(|h| p.matches(h)).unwrap_or(false))
+            .flat_map(|(_, hs)| hs.iter());
+        for (name, value) in self.headers.iter().chain(for_host) {
+            ret.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+            ret.push((name, value));
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_header_line() {
+        assert_eq!(
+            parse_header("X-Foo: bar: baz").unwrap(),
+            ("X-Foo".to_string(), "bar: baz".to_string())
+        );
+        assert_eq!(
+            parse_header("Accept:").unwrap(),
+            ("Accept".to_string(), "".to_string())
+        );
+        for input in &["X-Foo", ": bar", "X Foo: bar"] {
+            assert!(parse_header(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn match_host_pattern() {
+        let tests = &[
+            ("example.com", "example.com", true),
+            ("example.com", "EXAMPLE.com", true),
+            ("example.com", "foo.example.com", false),
+            ("*.example.com", "foo.example.com", true),
+            ("*.example.com", "foo.bar.example.com", true),
+            ("*.example.com", "example.com", false),
+            ("*.example.com", "fooexample.com", false),
+            ("*.Example.com", "foo.example.COM", true),
+        ];
+        for (pattern, host, want) in tests {
+            let p = HostPattern::new(pattern);
+            assert_eq!(p.matches(host), *want, "{:?} {:?}", pattern, host);
+        }
+    }
+
+    #[test]
+    fn headers_for_url() {
+        let headers = RequestHeaders::default()
+            .header("Accept", "*/*")
+            .header("X-Foo", "foo")
+            .host(
+                HostPattern::new("*.example.com"),
+                vec![("Authorization".to_string(), "Bearer xxx".to_string())],
+            )
+            .host(
+                HostPattern::new("docs.example.com"),
+                vec![("x-foo".to_string(), "docs".to_string())],
+            );
+
+        assert_eq!(
+            headers.for_url("https://github.com/foo"),
+            &[("Accept", "*/*"), ("X-Foo", "foo")],
+        );
+        assert_eq!(
+            headers.for_url("https://user@git.example.com:8080/foo"),
+            &[
+                ("Accept", "*/*"),
+                ("X-Foo", "foo"),
+                ("Authorization", "Bearer xxx")
+            ],
+        );
+        assert_eq!(
+            headers.for_url("https://docs.example.com"),
+            &[
+                ("Accept", "*/*"),
+                ("Authorization", "Bearer xxx"),
+                ("x-foo", "docs")
+            ],
+        );
+    }
+}
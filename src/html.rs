@@ -0,0 +1,539 @@
+use crate::extract::Span;
+use crate::url::{find_all_urls, UrlContext};
+use std::borrow::Cow;
+use std::path::Path;
+
+// Extensions of files parsed as HTML. Attributes in Vue templates are written in the same way. .jsx files are not
+// included since character references are not used in JavaScript strings
+pub(crate) const EXTENSIONS: &[&str] = &["html", "htm", "xhtml", "vue"];
+
+// Attributes whose values are URLs. Attributes starting with "data-" are also checked
+const URL_ATTRS: &[&str] = &["href", "src", "action", "cite"];
+
+pub fn is_html_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(e)))
+        .unwrap_or(false)
+}
+
+fn is_http_url(s: &str) -> bool {
+    let s = s.as_bytes();
+    let starts_with = |p: &[u8]| s.len() >= p.len() && s[..p.len()].eq_ignore_ascii_case(p);
+    starts_with(b"https://") || starts_with(b"http://")
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(num) = name.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => num.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => None,
+    }
+}
+
+// Decode character references such as &amp; and &#x26;. Unknown references are kept as-is
+pub fn decode_entities(s: &str) -> Cow<'_, str> {
+    if !s.contains('&') {
+        return Cow::Borrowed(s);
+    }
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let c = rest
+            .find(';')
+            .filter(|&end| end <= 32)
+            .and_then(|end| decode_entity(&rest[1..end]).map(|c| (c, end)));
+        match c {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+// Encode the string to be put in an attribute value quoted with ' or "
+pub fn encode_attribute(s: &str) -> Cow<'_, str> {
+    if !s.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(s);
+    }
+    let mut encoded = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        match c {
+            '&' => encoded.push_str("&amp;"),
+            '<' => encoded.push_str("&lt;"),
+            '>' => encoded.push_str("&gt;"),
+            '"' => encoded.push_str("&quot;"),
+            '\'' => encoded.push_str("&#39;"),
+            c => encoded.push(c),
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+struct Attr {
+    name: (usize, usize),
+    // Span of the value without quotes
+    value: Option<(usize, usize)>,
+    // The value is JSX expression like {url}. The span does not include the braces
+    expr: bool,
+}
+
+// Minimal HTML tokenizer to find attributes of start tags
+struct Scanner<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn skip_while(&mut self, pred: impl Fn(char) -> bool) {
+        let len = self.src[self.pos..]
+            .find(|c| !pred(c))
+            .unwrap_or(self.src.len() - self.pos);
+        self.pos += len;
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    // Parse attributes of the tag until '>'
+    fn attrs(&mut self) -> Vec<Attr> {
+        let mut attrs = vec![];
+        loop {
+            self.skip_while(|c| c.is_ascii_whitespace() || c == '/');
+            match self.peek() {
+                None => return attrs,
+                Some('>') => {
+                    self.pos += 1;
+                    return attrs;
+                }
+                _ => {}
+            }
+            let start = self.pos;
+            self.skip_while(|c| !c.is_ascii_whitespace() && !matches!(c, '=' | '>' | '/'));
+            if self.pos == start {
+                self.pos += 1; // Stray character such as '/' not followed by '>'
+                continue;
+            }
+            let name = (start, self.pos);
+            self.skip_while(|c| c.is_ascii_whitespace());
+            if self.peek() != Some('=') {
+                attrs.push(Attr {
+                    name,
+                    value: None,
+                    expr: false,
+                });
+                continue;
+            }
+            self.pos += 1;
+            self.skip_while(|c| c.is_ascii_whitespace());
+            let mut expr = false;
+            let value = match self.peek() {
+                Some(q @ ('"' | '\'')) => {
+                    let start = self.pos + 1;
+                    let end = self.src[start..]
+                        .find(q)
+                        .map(|i| start + i)
+                        .unwrap_or(self.src.len());
+                    self.pos = (end + 1).min(self.src.len());
+                    Some((start, end))
+                }
+                // JSX expression like href={url}
+                Some('{') => {
+                    expr = true;
+                    let start = self.pos + 1;
+                    let mut depth = 0;
+                    for (i, c) in self.src[self.pos..].char_indices() {
+                        match c {
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                        if depth == 0 {
+                            self.pos += i + 1;
+                            break;
+                        }
+                    }
+                    if depth > 0 {
+                        self.pos = self.src.len();
+                        Some((start, self.pos))
+                    } else {
+                        Some((start, self.pos - 1))
+                    }
+                }
+                _ => {
+                    let start = self.pos;
+                    self.skip_while(|c| !c.is_ascii_whitespace() && c != '>');
+                    Some((start, self.pos))
+                }
+            };
+            attrs.push(Attr { name, value, expr });
+        }
+    }
+}
+
+fn trim_span(src: &str, (start, end): (usize, usize)) -> (usize, usize) {
+    let s = &src[start..end];
+    let start = start + (s.len() - s.trim_start().len());
+    let end = end - (s.len() - s.trim_end().len());
+    (start, end.max(start))
+}
+
+// Find URLs in srcset attribute value like "a.png 1x, b.png 2x"
+// https://html.spec.whatwg.org/multipage/images.html#parsing-a-srcset-attribute
+fn srcset_urls(src: &str, (start, end): (usize, usize), urls: &mut Vec<(usize, usize)>) {
+    let mut pos = start;
+    while pos < end {
+        let s = &src[pos..end];
+        let skip = s.len()
+            - s.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',')
+                .len();
+        pos += skip;
+        let s = &src[pos..end];
+        let len = s.find(|c: char| c.is_ascii_whitespace()).unwrap_or(s.len());
+        let url = s[..len].trim_end_matches(',');
+        if is_http_url(url) {
+            urls.push((pos, pos + url.len()));
+        }
+        pos += len;
+        // Skip descriptors
+        if !s[..len].ends_with(',') {
+            pos = match src[pos..end].find(',') {
+                Some(i) => pos + i + 1,
+                None => end,
+            };
+        }
+    }
+}
+
+// Find URL in content attribute of <meta http-equiv="refresh" content="5; url=https://example.com">
+fn refresh_url(src: &str, (start, end): (usize, usize)) -> Option<(usize, usize)> {
+    let s = &src[start..end];
+    let i = s.find(';')? + 1;
+    let rest = s[i..].trim_start();
+    let mut pos = end - rest.len();
+    if rest.get(..3).is_some_and(|p| p.eq_ignore_ascii_case("url")) {
+        let after = rest[3..].trim_start();
+        let after = after.strip_prefix('=')?.trim_start();
+        pos = end - after.len();
+    }
+    let (start, mut end) = trim_span(src, (pos, end));
+    let mut start = start;
+    if let Some(q) = src[start..end]
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '"' | '\''))
+    {
+        start += 1;
+        if src[start..end].ends_with(q) {
+            end -= 1;
+        }
+    }
+    is_http_url(&src[start..end]).then_some((start, end))
+}
+
+// Find URLs in the range as plain text
+fn text_urls(src: &str, (start, end): (usize, usize), context: UrlContext, urls: &mut Vec<Span>) {
+    urls.extend(
+        find_all_urls(&src[start..end])
+            .into_iter()
+            .map(|(s, e)| Span::new(start + s, start + e, context)),
+    );
+}
+
+// Find spans of URLs in HTML document. Values of URL attributes such as href and srcset are parsed as URLs. URLs in
+// text and other attribute values are found as plain text. Spans in them are raw texts in the source so they may
+// contain character references like &amp; (UrlContext::Html). Comments, contents of <script> and <style>, and JSX
+// expressions are searched as plain text without character references (UrlContext::Text)
+pub fn find_html_urls(content: &str) -> Vec<Span> {
+    let mut urls = vec![];
+    let mut scanner = Scanner {
+        src: content,
+        pos: 0,
+    };
+    // Start of the text after the last tag
+    let mut text_start = 0;
+    while let Some(i) = content[scanner.pos..].find('<') {
+        let tag_start = scanner.pos + i;
+        scanner.pos = tag_start + 1;
+        let rest = &content[scanner.pos..];
+        if rest.starts_with("!--") {
+            text_urls(
+                content,
+                (text_start, tag_start),
+                UrlContext::Html,
+                &mut urls,
+            );
+            let start = scanner.pos + 3;
+            let end = content[start..]
+                .find("-->")
+                .map(|i| start + i)
+                .unwrap_or(content.len());
+            text_urls(content, (start, end), UrlContext::Text, &mut urls);
+            scanner.pos = (end + 3).min(content.len());
+            text_start = scanner.pos;
+            continue;
+        }
+        if !rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue; // End tag, doctype or '<' in text
+        }
+        text_urls(
+            content,
+            (text_start, tag_start),
+            UrlContext::Html,
+            &mut urls,
+        );
+
+        let start = scanner.pos;
+        scanner.skip_while(|c| !c.is_ascii_whitespace() && !matches!(c, '>' | '/'));
+        let tag = content[start..scanner.pos].to_ascii_lowercase();
+        let attrs = scanner.attrs();
+        let name_of = |a: &Attr| content[a.name.0..a.name.1].to_ascii_lowercase();
+
+        let is_refresh = tag == "meta"
+            && attrs.iter().any(|a| {
+                name_of(a) == "http-equiv"
+                    && a.value
+                        .map(|(s, e)| content[s..e].trim().eq_ignore_ascii_case("refresh"))
+                        .unwrap_or(false)
+            });
+        for attr in attrs.iter() {
+            let value = match attr.value {
+                Some(v) => v,
+                None => continue,
+            };
+            if attr.expr {
+                text_urls(content, value, UrlContext::Text, &mut urls);
+                continue;
+            }
+            let name = name_of(attr);
+            let mut found = vec![];
+            if URL_ATTRS.contains(&name.as_str()) || name.starts_with("data-") {
+                let (s, e) = trim_span(content, value);
+                let v = &content[s..e];
+                if is_http_url(v) && !v.contains(|c: char| c.is_ascii_whitespace()) {
+                    found.push((s, e));
+                }
+            } else if name == "srcset" {
+                srcset_urls(content, value, &mut found);
+            } else if name == "content" && is_refresh {
+                found.extend(refresh_url(content, value));
+            }
+            if found.is_empty() {
+                text_urls(content, value, UrlContext::Html, &mut urls);
+            } else {
+                urls.extend(
+                    found
+                        .into_iter()
+                        .map(|(s, e)| Span::new(s, e, UrlContext::Html)),
+                );
+            }
+        }
+        text_start = scanner.pos;
+
+        // Contents of <script> and <style> are raw text where character references are not decoded
+        if tag == "script" || tag == "style" {
+            let start = scanner.pos;
+            let end = content[start..]
+                .find(&format!("</{}", tag))
+                .map(|i| start + i)
+                .unwrap_or(content.len());
+            text_urls(content, (start, end), UrlContext::Text, &mut urls);
+            scanner.pos = end;
+            text_start = end;
+        }
+    }
+    text_urls(
+        content,
+        (text_start, content.len()),
+        UrlContext::Html,
+        &mut urls,
+    );
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_urls(content: &str) -> Vec<&str> {
+        find_html_urls(content)
+            .into_iter()
+            .map(|s| &content[s.start..s.end])
+            .collect()
+    }
+
+    #[test]
+    fn find_urls_in_attributes() {
+        let tests: &[(&str, &[&str])] = &[
+            (
+                r#"<a href="https://example.com/foo">https://example.com/text</a>"#,
+                &["https://example.com/foo", "https://example.com/text"],
+            ),
+            (
+                r#"<A HREF='https://example.com/a' title="see https://example.com/b.">"#,
+                &["https://example.com/a", "https://example.com/b"],
+            ),
+            (
+                "<img src=https://example.com/a.png alt=x>",
+                &["https://example.com/a.png"],
+            ),
+            (
+                r#"<a href=" https://example.com/trim ">"#,
+                &["https://example.com/trim"],
+            ),
+            (
+                r#"<form action="https://example.com/post"><blockquote cite="https://example.com/quote">"#,
+                &["https://example.com/post", "https://example.com/quote"],
+            ),
+            (
+                r#"<div data-url="https://example.com/data" data-name="foo">"#,
+                &["https://example.com/data"],
+            ),
+            (
+                r#"<a href="https://example.com/?a=1&amp;b=2">"#,
+                &["https://example.com/?a=1&amp;b=2"],
+            ),
+            (
+                r#"<a href="https://en.wikipedia.org/wiki/Rust_(programming_language)">"#,
+                &["https://en.wikipedia.org/wiki/Rust_(programming_language)"],
+            ),
+            (
+                r#"<img srcset="https://example.com/a.png 1x,https://example.com/b,c.png 2x, /local.png 3x">"#,
+                &["https://example.com/a.png", "https://example.com/b,c.png"],
+            ),
+            (
+                r#"<meta http-equiv="Refresh" content="5; URL='https://example.com/moved'">"#,
+                &["https://example.com/moved"],
+            ),
+            (
+                r#"<meta http-equiv="refresh" content="0;https://example.com/moved">"#,
+                &["https://example.com/moved"],
+            ),
+            (r#"<meta http-equiv="refresh" content="0; a€">"#, &[]),
+            (r#"<meta http-equiv="refresh" content="0; €">"#, &[]),
+            (
+                r#"<meta name="description" content="0; url=https://example.com">"#,
+                &["https://example.com"],
+            ),
+            (r#"<a href="/relative">"#, &[]),
+            (
+                r#"<a :href="'https://example.com/vue'">"#,
+                &["https://example.com/vue"],
+            ),
+            (
+                r#"<a href={url} title="x" src="https://example.com/jsx">"#,
+                &["https://example.com/jsx"],
+            ),
+            (
+                r#"<a href={"https://example.com/" + {a: 1}.a} rel="x">"#,
+                &["https://example.com/"],
+            ),
+            (
+                r#"<!-- <a href="https://example.com/comment"> --><a href="https://example.com/a">"#,
+                &["https://example.com/comment", "https://example.com/a"],
+            ),
+            (
+                r#"<script>var s = '<a href="https://example.com/script">'</script><a href="https://example.com/a">"#,
+                &["https://example.com/script", "https://example.com/a"],
+            ),
+            (
+                r#"a < b and <a href="https://example.com/a""#,
+                &["https://example.com/a"],
+            ),
+        ];
+        for (input, want) in tests {
+            assert_eq!(&find_urls(input), want, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn contexts_of_urls() {
+        let content = r#"<p>https://example.com/a?x=1&amp;y=2</p>
+<!-- https://example.com/b?x=1&amp;y=2 -->
+<script>
+// https://example.com/c
+</script>
+<style>@import url(https://example.com/d)</style>
+<a href="https://example.com/e">"#;
+        let have: Vec<_> = find_html_urls(content)
+            .into_iter()
+            .map(|s| (&content[s.start..s.end], s.context))
+            .collect();
+        assert_eq!(
+            have,
+            &[
+                ("https://example.com/a?x=1&amp;y=2", UrlContext::Html),
+                ("https://example.com/b?x=1&amp;y=2", UrlContext::Text),
+                ("https://example.com/c", UrlContext::Text),
+                ("https://example.com/d", UrlContext::Text),
+                ("https://example.com/e", UrlContext::Html),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_and_encode_entities() {
+        let tests = &[
+            ("https://example.com", "https://example.com"),
+            (
+                "https://example.com/?a=1&amp;b=2",
+                "https://example.com/?a=1&b=2",
+            ),
+            (
+                "https://example.com/?a=1&#38;b=2&#x26;c",
+                "https://example.com/?a=1&b=2&c",
+            ),
+            (
+                "https://example.com/?a=1&b=2",
+                "https://example.com/?a=1&b=2",
+            ),
+            ("&unknown; &amp &lt;&quot;", "&unknown; &amp <\""),
+        ];
+        for (input, want) in tests {
+            assert_eq!(decode_entities(input), *want, "{:?}", input);
+        }
+
+        assert_eq!(
+            encode_attribute("https://example.com/?a=1&b='2'"),
+            "https://example.com/?a=1&amp;b=&#39;2&#39;"
+        );
+        assert!(matches!(
+            encode_attribute("https://example.com"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn detect_html_file() {
+        for (path, want) in &[
+            ("index.html", true),
+            ("index.HTM", true),
+            ("App.vue", true),
+            ("App.jsx", false),
+            ("main.js", false),
+        ] {
+            assert_eq!(is_html_file(Path::new(path)), *want, "{:?}", path);
+        }
+    }
+}
@@ -1,7 +1,8 @@
 //! This is a library part of [fixred][repo] tool.
 //!
 //! To install as dependency, add `fixred` to your `Cargo.toml` file. Ensure to disable default features.
-//! It removes all unnecessary dependencies for using this tool as library.
+//! It removes all unnecessary dependencies for using this tool as library. `config`, `mapping` and `report` features
+//! enable the modules of the same names.
 //!
 //! ```toml
 //! [dependencies]
@@ -19,15 +20,53 @@
 //! eprintln!("Fixed {} link(s)", fixed);
 //! ```
 //!
+//! Resolvers can be composed with methods of `ResolverExt` trait. `MappingResolver` requires `mapping` feature.
+//!
+//! ```
+//! # #[cfg(feature = "mapping")]
+//! # {
+//! use fixred::combinator::ResolverExt;
+//! use fixred::mapping::MappingResolver;
+//! use fixred::redirect::Redirector;
+//! use fixred::resolve::CurlResolver;
+//! use fixred::rule::{Rule, RuleResolver};
+//!
+//! let rules = RuleResolver::new(vec![Rule::new("^http://", "https://").unwrap()]);
+//! let mapping: MappingResolver = vec![("https://old.example.com/*", "https://new.example.com/*")]
+//!     .into_iter()
+//!     .collect();
+//! // Try the rules, the mapping and sending requests in order
+//! let resolver = rules.chain(mapping).chain(CurlResolver::default());
+//! let red = Redirector::new(resolver);
+//! # }
+//! ```
+//!
 //! For the real world example, please see [src][] directory.
 //!
 //! [repo]: https://github.com/rhysd/fixred
 //! [src]: https://github.com/rhysd/fixred/tree/main/src
 
+pub mod cache;
+pub mod combinator;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod extract;
+pub mod header;
+pub mod html;
+pub mod limit;
+#[cfg(feature = "mapping")]
+pub mod mapping;
+pub mod markdown;
 pub mod redirect;
 pub mod replace;
+#[cfg(feature = "report")]
+pub mod report;
 pub mod resolve;
+pub mod rule;
+pub mod source;
 pub mod url;
 
 #[cfg(test)]
 mod test_helper;
+#[cfg(test)]
+mod test_server;
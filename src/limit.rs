@@ -0,0 +1,322 @@
+use crate::url::find_host;
+use anyhow::{Context, Result};
+use log::debug;
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Limits of requests to one host
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Limit {
+    // Maximum number of in-flight requests. None means unlimited
+    pub concurrency: Option<usize>,
+    // Maximum number of requests per second. None means unlimited
+    pub rate: Option<f64>,
+}
+
+// Requests to one host are sent at least once in this interval. Lower rates are rejected since such a long interval
+// would make the run look hung (and 1 / rate can overflow Duration)
+const MAX_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Parse the number of requests per second. It must be a positive number
+pub fn parse_rate(s: &str) -> Result<f64> {
+    match s.trim().parse::<f64>() {
+        Ok(r) if r > 0.0 && interval(r) < MAX_INTERVAL => Ok(r),
+        Ok(r) if r > 0.0 => anyhow::bail!(
+            "Requests per second must be at least one request per {:?} but got {:?}",
+            MAX_INTERVAL,
+            s
+        ),
+        _ => anyhow::bail!(
+            "Requests per second must be a positive number but got {:?}",
+            s
+        ),
+    }
+}
+
+// Parse the maximum number of in-flight requests. 0 means unlimited
+pub fn parse_concurrency(s: &str) -> Result<Option<usize>> {
+    let n: usize = s
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid number of in-flight requests {:?}", s))?;
+    Ok((n > 0).then_some(n))
+}
+
+// Interval between requests for the rate. It is capped by MAX_INTERVAL
+fn interval(rate: f64) -> Duration {
+    Duration::try_from_secs_f64(1.0 / rate)
+        .unwrap_or(MAX_INTERVAL)
+        .min(MAX_INTERVAL)
+}
+
+impl Limit {
+    // Parse limit as CONCURRENCY[:RATE]. Either part can be empty to make it unlimited. Concurrency 0 also means
+    // unlimited
+    pub fn parse(s: &str) -> Result<Self> {
+        let (concurrency, rate) = s.split_once(':').unwrap_or((s, ""));
+        let concurrency = match concurrency.trim() {
+            "" => None,
+            c => parse_concurrency(c).with_context(|| format!("Invalid limit {:?}", s))?,
+        };
+        let rate = match rate.trim() {
+            "" => None,
+            r => Some(parse_rate(r).with_context(|| format!("Invalid limit {:?}", s))?),
+        };
+        Ok(Self { concurrency, rate })
+    }
+}
+
+#[derive(Default)]
+struct HostState {
+    in_flight: usize,
+    // The time when the next request to the host can be sent
+    next_slot: Option<Instant>,
+}
+
+#[derive(Default)]
+struct State {
+    in_flight: usize,
+    hosts: HashMap<String, HostState>,
+}
+
+// Scheduler of requests which limits in-flight requests and requests per second for each host. Requests are blocked
+// until they can be sent
+#[derive(Default)]
+pub struct HostLimiter {
+    default: Limit,
+    overrides: HashMap<String, Limit>,
+    // Maximum number of in-flight requests to all hosts
+    global: Option<usize>,
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+impl HostLimiter {
+    pub fn new(default: Limit) -> Self {
+        Self {
+            default,
+            ..Self::default()
+        }
+    }
+
+    pub fn global_concurrency(mut self, max: Option<usize>) -> Self {
+        self.global = max;
+        self
+    }
+
+    // Set limit for the specific host instead of the default limit
+    pub fn host(mut self, host: &str, limit: Limit) -> Self {
+        debug!("Limit for host {}: {:?}", host, limit);
+        self.overrides.insert(host.to_ascii_lowercase(), limit);
+        self
+    }
+
+    // Parse HOST=CONCURRENCY[:RATE] and set the limit for the host
+    pub fn parse_host(self, s: &str) -> Result<Self> {
+        let (host, limit) = s.split_once('=').with_context(|| {
+            format!("Host limit must be HOST=CONCURRENCY[:RATE] but got {:?}", s)
+        })?;
+        Ok(self.host(host.trim(), Limit::parse(limit)?))
+    }
+
+    fn limit_of(&self, host: &str) -> &Limit {
+        self.overrides.get(host).unwrap_or(&self.default)
+    }
+
+    // Wait until a request to the URL can be sent. The request is treated as in-flight until the returned permit is
+    // dropped
+    pub fn acquire(&self, url: &str) -> Permit<'_> {
+        let host = find_host(url).unwrap_or("").to_ascii_lowercase();
+        let limit = *self.limit_of(&host);
+
+        let mut state = self.state.lock().unwrap();
+        loop {
+            // Maximum 0 means unlimited since no request could be sent otherwise
+            let global_ok = self
+                .global
+                .map(|m| m == 0 || state.in_flight < m)
+                .unwrap_or(true);
+            let in_flight = state.hosts.get(&host).map(|h| h.in_flight).unwrap_or(0);
+            let host_ok = limit
+                .concurrency
+                .map(|m| m == 0 || in_flight < m)
+                .unwrap_or(true);
+            if global_ok && host_ok {
+                break;
+            }
+            state = self.released.wait(state).unwrap();
+        }
+
+        state.in_flight += 1;
+        let now = Instant::now();
+        let h = state.hosts.entry(host.clone()).or_default();
+        h.in_flight += 1;
+        // Reserve the next slot while holding the lock so that concurrent requests are spaced by the rate
+        let wait = limit.rate.map(|rate| {
+            let slot = h.next_slot.map(|s| s.max(now)).unwrap_or(now);
+            h.next_slot = Some(slot + interval(rate));
+            slot - now
+        });
+        drop(state);
+
+        if let Some(wait) = wait {
+            if !wait.is_zero() {
+                debug!("Waiting {:?} for rate limit of host {}", wait, host);
+                thread::sleep(wait);
+            }
+        }
+
+        Permit {
+            limiter: self,
+            host,
+        }
+    }
+
+    fn release(&self, host: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        if let Some(h) = state.hosts.get_mut(host) {
+            h.in_flight -= 1;
+        }
+        self.released.notify_all();
+    }
+}
+
+pub struct Permit<'a> {
+    limiter: &'a HostLimiter,
+    host: String,
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        self.limiter.release(&self.host);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn parse_limit() {
+        let tests = &[
+            ("4", Some(4), None),
+            ("4:2.5", Some(4), Some(2.5)),
+            (":10", None, Some(10.0)),
+            ("", None, None),
+            ("0:1", None, Some(1.0)),
+        ];
+        for (input, concurrency, rate) in tests {
+            let want = Limit {
+                concurrency: *concurrency,
+                rate: *rate,
+            };
+            assert_eq!(Limit::parse(input).unwrap(), want, "{:?}", input);
+        }
+
+        for input in &["x", "4:y", "4:0", "-1", "4:1e-20"] {
+            assert!(Limit::parse(input).is_err(), "{:?}", input);
+        }
+
+        let l = HostLimiter::default().parse_host("GitHub.com=2:1").unwrap();
+        assert_eq!(
+            l.limit_of("github.com"),
+            &Limit {
+                concurrency: Some(2),
+                rate: Some(1.0)
+            }
+        );
+        assert_eq!(l.limit_of("example.com"), &Limit::default());
+        assert!(HostLimiter::default().parse_host("github.com").is_err());
+    }
+
+    #[test]
+    fn parse_rate_and_concurrency() {
+        assert_eq!(parse_rate("0.5").unwrap(), 0.5);
+        for input in &["0", "-1", "1e-20", "NaN", "x"] {
+            assert!(parse_rate(input).is_err(), "{:?}", input);
+        }
+        assert_eq!(parse_concurrency("0").unwrap(), None);
+        assert_eq!(parse_concurrency("3").unwrap(), Some(3));
+        assert!(parse_concurrency("-1").is_err());
+    }
+
+    #[test]
+    fn zero_concurrency_is_unlimited() {
+        // Limits set via library API are not parsed. This must not hang
+        let limiter = HostLimiter::new(Limit {
+            concurrency: Some(0),
+            rate: None,
+        })
+        .global_concurrency(Some(0));
+        let urls = &["https://example.com/a"; 4];
+        assert_eq!(max_in_flight(&limiter, urls), 4);
+        assert_eq!(interval(1e-20), MAX_INTERVAL);
+    }
+
+    fn max_in_flight(limiter: &HostLimiter, urls: &[&str]) -> usize {
+        let current = &AtomicUsize::new(0);
+        let max = &AtomicUsize::new(0);
+        thread::scope(|s| {
+            for url in urls {
+                s.spawn(move || {
+                    let _permit = limiter.acquire(url);
+                    let n = current.fetch_add(1, Ordering::SeqCst) + 1;
+                    max.fetch_max(n, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    current.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        max.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn limit_concurrency_per_host() {
+        let limiter = HostLimiter::new(Limit {
+            concurrency: Some(2),
+            rate: None,
+        });
+        let urls = &["https://example.com/a"; 8];
+        assert_eq!(max_in_flight(&limiter, urls), 2);
+
+        let limiter = limiter.host(
+            "example.com",
+            Limit {
+                concurrency: Some(1),
+                rate: None,
+            },
+        );
+        assert_eq!(max_in_flight(&limiter, urls), 1);
+        assert_eq!(limiter.state.lock().unwrap().in_flight, 0);
+    }
+
+    #[test]
+    fn limit_global_concurrency() {
+        let limiter = HostLimiter::default().global_concurrency(Some(3));
+        let urls: Vec<_> = (0..8)
+            .map(|i| format!("https://{}.example.com", i))
+            .collect();
+        let urls: Vec<_> = urls.iter().map(String::as_str).collect();
+        assert_eq!(max_in_flight(&limiter, &urls), 3);
+    }
+
+    #[test]
+    fn limit_requests_per_second() {
+        let limiter = HostLimiter::new(Limit {
+            concurrency: None,
+            rate: Some(50.0),
+        });
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire("https://example.com/a");
+        }
+        // Other hosts are not affected
+        limiter.acquire("https://example.org/a");
+        // 4 intervals of 20ms
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+}
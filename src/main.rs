@@ -1,11 +1,31 @@
 use anyhow::{Context, Result};
+use clap::ArgMatches;
 use clap::{App, Arg};
-use fixred::redirect::CurlRedirector;
+use fixred::cache::DiskCache;
+use fixred::combinator::Chain;
+use fixred::config::Config;
+use fixred::header::{parse_header, RequestHeaders};
+use fixred::limit::{parse_concurrency, parse_rate, HostLimiter, Limit};
+use fixred::mapping::{write_mapping, MappingFormat, MappingResolver};
+use fixred::redirect::{FileLinks, FileReplacements, Redirector};
+use fixred::replace::write_unified_diff;
+use fixred::report::{write_broken_links, write_json, write_json_lines, write_sarif};
+use fixred::resolve::{CurlResolver, Method, Resolver, TlsVersion, PERMANENT_REDIRECTS};
+use fixred::rule::Rewrite;
 use log::{debug, info, log_enabled, Level, LevelFilter};
 use regex::Regex;
+use std::convert::TryInto;
 use std::env;
-use std::io;
-use std::time;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::{self, Duration, SystemTime};
+
+// Exit status when outdated links are found with --check
+const EXIT_OUTDATED_LINKS: i32 = 1;
+const EXIT_ERROR: i32 = 2;
 
 fn build_logger(verbose: bool) -> env_logger::Builder {
     let mut builder = env_logger::Builder::from_env("FIXRED_LOG");
@@ -16,7 +36,309 @@ fn build_logger(verbose: bool) -> env_logger::Builder {
     builder
 }
 
-fn main() -> Result<()> {
+fn print_replacements<W: Write>(mut out: W, files: &[FileReplacements]) -> Result<()> {
+    for file in files {
+        for rep in file.replacements.iter() {
+            let (line, col) = rep.position(&file.content);
+            let url = &file.content[rep.start..rep.end];
+            writeln!(
+                out,
+                "{}:{}:{}: {} -> {}",
+                file.path.display(),
+                line,
+                col,
+                url,
+                rep.text,
+            )?;
+        }
+    }
+    Ok(out.flush()?)
+}
+
+fn parse_status_codes(codes: &str) -> Result<Vec<u32>> {
+    codes
+        .split(',')
+        .map(|c| {
+            let c = c.trim();
+            c.parse()
+                .with_context(|| format!("Invalid status code {:?} in {:?}", c, codes))
+        })
+        .collect()
+}
+
+fn parse_number(matches: &ArgMatches, name: &str) -> Result<u64> {
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse()
+        .with_context(|| format!("Invalid number {:?} for --{}", value, name))
+}
+
+fn build_limiter(matches: &ArgMatches) -> Result<HostLimiter> {
+    let concurrency = parse_concurrency(matches.value_of("host-concurrency").unwrap())
+        .context("Invalid --host-concurrency option")?;
+    let rate = matches.value_of("host-rate").map(parse_rate).transpose()?;
+    let limit = Limit { concurrency, rate };
+    let global = match matches.value_of("concurrency") {
+        Some(c) => parse_concurrency(c).context("Invalid --concurrency option")?,
+        None => None,
+    };
+
+    let mut limiter = HostLimiter::new(limit).global_concurrency(global);
+    if let Some(specs) = matches.values_of("host-limit") {
+        for spec in specs {
+            limiter = limiter.parse_host(spec)?;
+        }
+    }
+    Ok(limiter)
+}
+
+fn build_headers(matches: &ArgMatches, config: &Config) -> Result<RequestHeaders> {
+    let mut headers = RequestHeaders::default();
+    if let Some(lines) = matches.values_of("header") {
+        for line in lines {
+            let (name, value) = parse_header(line)?;
+            headers = headers.header(name, value);
+        }
+    }
+    config.request_headers(headers)
+}
+
+fn configure_tls(mut resolver: CurlResolver, matches: &ArgMatches) -> Result<CurlResolver> {
+    if let Some(url) = matches.value_of("proxy") {
+        resolver = resolver.proxy(url);
+    }
+    if let Some(hosts) = matches.value_of("no-proxy") {
+        resolver = resolver.no_proxy(hosts);
+    }
+    if let Some(path) = matches.value_of_os("cacert") {
+        resolver = resolver.ca_cert(path);
+    }
+    if let Some(path) = matches.value_of_os("cert") {
+        let key = matches.value_of_os("key").map(PathBuf::from);
+        resolver = resolver.client_cert(path, key);
+    }
+    if let Some(version) = matches.value_of("tls-min-version") {
+        let version: TlsVersion = version.parse().map_err(anyhow::Error::msg)?;
+        resolver = resolver.min_tls_version(version);
+    }
+    if matches.is_present("insecure") {
+        eprintln!(
+            "WARNING: --insecure disables verifying TLS certificates. Connections to servers can be intercepted and \
+             redirects resolved via them cannot be trusted. Use --cacert instead if possible"
+        );
+        resolver = resolver.insecure(true);
+    }
+    Ok(resolver)
+}
+
+fn open_cache(matches: &ArgMatches) -> Result<Option<Arc<DiskCache>>> {
+    let commands = ["cache-list", "cache-prune", "cache-clear"];
+    let enabled = matches.is_present("cache")
+        || matches.is_present("cache-file")
+        || commands.iter().any(|c| matches.is_present(c));
+    if !enabled {
+        return Ok(None);
+    }
+    let path = match matches.value_of_os("cache-file") {
+        Some(p) => PathBuf::from(p),
+        None => DiskCache::default_path()
+            .context("Could not determine cache directory. Please specify --cache-file")?,
+    };
+    let positive = Duration::from_secs(parse_number(matches, "cache-ttl")?);
+    let negative = Duration::from_secs(parse_number(matches, "cache-negative-ttl")?);
+    let cache = DiskCache::open(path)?.ttl(positive, negative);
+    Ok(Some(Arc::new(cache)))
+}
+
+fn print_cache_entries<W: Write>(mut out: W, cache: &DiskCache) -> Result<()> {
+    let now = SystemTime::now();
+    for (url, entry) in cache.entries() {
+        let expired = if cache.is_expired(&entry, now) {
+            ", expired"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "{}: {} (cached at {}{})",
+            url,
+            entry.resolution.outcome,
+            httpdate::fmt_http_date(entry.cached_at()),
+            expired,
+        )?;
+    }
+    Ok(out.flush()?)
+}
+
+fn read_stdin() -> Result<String> {
+    let mut content = String::new();
+    io::stdin()
+        .read_to_string(&mut content)
+        .context("While reading stdin")?;
+    Ok(content)
+}
+
+fn find_links<R: Resolver>(red: &Redirector<R>, matches: &ArgMatches) -> Result<Vec<FileLinks>> {
+    if let Some(paths) = matches.values_of_os("PATH") {
+        info!("Finding links in all files in given paths via command line arguments");
+        return red.find_all_links(paths);
+    }
+
+    info!("Finding links in stdin");
+    let content = read_stdin()?;
+    let links = red.find_links(&content);
+    Ok(vec![FileLinks {
+        path: PathBuf::from("<stdin>"),
+        content,
+        links,
+    }])
+}
+
+fn find_replacements<R: Resolver>(
+    red: &Redirector<R>,
+    matches: &ArgMatches,
+) -> Result<Vec<FileReplacements>> {
+    if let Some(paths) = matches.values_of_os("PATH") {
+        info!("Finding redirects in all files in given paths via command line arguments");
+        return red.find_all_replacements(paths);
+    }
+
+    info!("Finding redirects in stdin");
+    let content = read_stdin()?;
+    let replacements = red.find_replacements(&content);
+    Ok(vec![FileReplacements {
+        path: PathBuf::from("<stdin>"),
+        content,
+        replacements,
+    }])
+}
+
+// Fix links or output them with the resolver. Returns true when outdated links were found in check mode
+fn process<R: Resolver>(resolver: R, matches: &ArgMatches) -> Result<bool> {
+    let export = matches
+        .value_of_os("export-mapping")
+        .map(|p| -> Result<_> {
+            let path = Path::new(p);
+            Ok((path, MappingFormat::from_path(path)?))
+        })
+        .transpose()?;
+    let red = Redirector::new(resolver)
+        .extract(matches.value_of("extract").map(Regex::new).transpose()?)
+        .ignore(matches.value_of("ignore").map(Regex::new).transpose()?)
+        .markdown(
+            matches
+                .value_of("markdown")
+                .unwrap()
+                .parse()
+                .map_err(anyhow::Error::msg)?,
+        )
+        .markdown_code(matches.is_present("markdown-code"))
+        .source(
+            matches
+                .value_of("source")
+                .unwrap()
+                .parse()
+                .map_err(anyhow::Error::msg)?,
+        )
+        .shallow(matches.is_present("shallow"))
+        .record_resolved(export.is_some());
+
+    let check = matches.is_present("check");
+    // Number of outdated links and files which contain them
+    let mut outdated = (0, 0);
+    if matches.value_of("format") == Some("sarif") {
+        let files = find_replacements(&red, matches)?;
+        let stdout = io::stdout();
+        write_sarif(stdout.lock(), &files)?;
+
+        let count = files.iter().map(|f| f.replacements.len()).sum();
+        outdated = (count, files.len());
+    } else if let Some(format) = matches.value_of("format") {
+        let files = find_links(&red, matches)?;
+        let stdout = io::stdout();
+        match format {
+            "json" => write_json(stdout.lock(), &files)?,
+            "jsonl" => write_json_lines(stdout.lock(), &files)?,
+            _ => unreachable!(),
+        }
+
+        for file in files.iter() {
+            let count = file.links.iter().filter_map(|l| l.replacement()).count();
+            if count > 0 {
+                outdated = (outdated.0 + count, outdated.1 + 1);
+            }
+        }
+    } else if check || matches.is_present("dry-run") || matches.is_present("diff") {
+        if matches.is_present("diff") && !matches.is_present("PATH") {
+            // Diff of stdin has no file to apply it to
+            anyhow::bail!("--diff requires file paths. It cannot be used with stdin");
+        }
+        let files = find_replacements(&red, matches)?;
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        if matches.is_present("diff") {
+            for file in files.iter() {
+                write_unified_diff(&mut out, &file.path, &file.content, &file.replacements)?;
+            }
+        } else {
+            print_replacements(&mut out, &files)?;
+        }
+
+        let count = files.iter().map(|f| f.replacements.len()).sum();
+        outdated = (count, files.len());
+    } else if let Some(paths) = matches.values_of_os("PATH") {
+        info!("Processing all files in given paths via command line arguments");
+        let count = red.fix_all_files(paths)?;
+        info!("Processed {} files", count);
+    } else {
+        info!("Fixing redirects in stdin");
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        let count = red
+            .fix(stdin.lock(), stdout.lock())
+            .context("While processing stdin")?;
+        info!("Fixed {} links in stdin", count);
+    }
+
+    if let Some((path, format)) = export {
+        let urls = red.resolved_urls();
+        info!("Exporting {} resolved URLs to {:?}", urls.len(), path);
+        let file = File::create(path)
+            .with_context(|| format!("Could not create mapping file {:?}", path))?;
+        write_mapping(BufWriter::new(file), format, &urls)
+            .with_context(|| format!("Could not write mapping file {:?}", path))?;
+    }
+
+    let summary = red.summary();
+    info!(
+        "Resolved {} links: {} redirected, {} not redirected, {} skipped, {} broken",
+        summary.total(),
+        summary.redirected,
+        summary.not_redirected,
+        summary.skipped,
+        summary.broken.len(),
+    );
+    if matches.is_present("report-broken") {
+        write_broken_links(io::stderr(), &summary)?;
+    } else {
+        for link in summary.broken.iter() {
+            info!("Broken link {}: {}", link.url, link.outcome);
+        }
+    }
+
+    if check && outdated.0 > 0 {
+        eprintln!(
+            "Found {} outdated link(s) in {} file(s)",
+            outdated.0, outdated.1
+        );
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+// Returns true when outdated links were found in check mode
+fn run() -> Result<bool> {
     let matches = App::new("fixred")
         .version(env!("CARGO_PKG_VERSION"))
         .about(
@@ -25,7 +347,37 @@ fn main() -> Result<()> {
             positives on extracting URLs in text files.\n\n\
             fixred follows redirects repeatedly and uses the last URL to replace. The behavior can be \
             changed by --shallow flag to resolve the first redirect only.\n\n\
+            fixred only follows permanent redirects (status 301 and 308) by default since temporary \
+            redirects such as login pages should not be applied. Status codes of redirects to follow \
+            can be changed by --allow-status option.\n\n\
+            fixred sends HEAD requests to resolve redirects and retries with GET requests when servers \
+            reject HEAD requests. This can be changed by --method option.\n\n\
+            Requests are retried with exponential backoff on temporary failures. Timeouts and retries \
+            can be configured with --connect-timeout, --timeout, --retry and --retry-backoff options.\n\n\
+            To avoid being rate-limited by servers, in-flight requests and requests per second to each \
+            host can be limited with --host-concurrency, --host-rate, --concurrency and --host-limit options.\n\n\
+            To reuse resolved redirects across runs, use --cache flag. The cache file can be inspected, \
+            pruned and cleared with --cache-list, --cache-prune and --cache-clear flags.\n\n\
+            Known migrations of URLs can be written as rules with regular expressions in a config \
+            file given by --config option. To resolve redirects without network, use --offline flag \
+            with the config file or a mapping file given by --mapping option. The mapping file can be \
+            generated from resolved redirects with --export-mapping option.\n\n\
+            Headers sent with requests can be added with --header and --user-agent options. Headers and \
+            tokens read from environment variables for specific hosts can be defined in the config file.\n\n\
+            Proxy, CA bundle, client certificate and minimum TLS version can be configured with --proxy, \
+            --no-proxy, --cacert, --cert, --key and --tls-min-version options.\n\n\
+            Markdown files are parsed as CommonMark to find URLs in links, images and reference definitions. \
+            URLs in code spans and code blocks are kept as-is. See --markdown and --markdown-code options.\n\n\
+            To keep URLs in string literals of source files such as API endpoints, use --source option.\n\n\
+            HTML files (.html, .htm, .xhtml and .vue) are parsed to find URLs in text and attributes \
+            such as href and srcset. Character references like &amp; in them are decoded and encoded again on \
+            writing back.\n\n\
             Filtering URLs to be fixed is supported. See descriptions of --extract and --ignore options.\n\n\
+            To know what would be fixed before modifying files, use --dry-run flag or --diff flag.\n\n\
+            To output a report of all links in JSON or outdated links in SARIF, use --format option.\n\n\
+            fixred can also report broken links with --report-broken flag while fixing outdated links.\n\n\
+            To check outdated links on CI, use --check flag. It exits with status 1 when some outdated \
+            links are found, and with status 2 when an error occurs.\n\n\
             To enable verbose output, use --verbose flag or set $FIXRED_LOG environment variable. \
             Setting --verbose or FIXRED_LOG=info outputs which file is being processed. Setting \
             FIXRED_LOG=debug outputs what fixred is doing.\n\n\
@@ -38,6 +390,287 @@ fn main() -> Result<()> {
                 .long("shallow")
                 .about("Redirect only once when resolving a URL redirect")
         )
+        .arg(
+            Arg::new("allow-status")
+                .long("allow-status")
+                .takes_value(true)
+                .value_name("CODES")
+                .default_value("301,308")
+                .about(
+                    "Comma-separated status codes of redirects to follow. For example, \
+                    \"301,302,303,307,308\" follows all redirects including temporary ones",
+                )
+        )
+        .arg(
+            Arg::new("only-permanent")
+                .long("only-permanent")
+                .about(
+                    "Follow only permanent redirects (status 301 and 308). This is the same as \
+                    --allow-status 301,308",
+                )
+        )
+        .arg(
+            Arg::new("method")
+                .long("method")
+                .takes_value(true)
+                .value_name("METHOD")
+                .possible_values(["head", "get", "head-then-get"])
+                .default_value("head-then-get")
+                .about(
+                    "HTTP method to resolve redirects. \"head-then-get\" sends HEAD request first and \
+                    retries with GET request when the server rejects HEAD request with status 403, \
+                    404, 405 or 501. GET request is aborted after receiving response headers",
+                )
+        )
+        .arg(
+            Arg::new("connect-timeout")
+                .long("connect-timeout")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .default_value("10")
+                .about("Timeout in seconds for connecting to a server. 0 means no timeout")
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .default_value("30")
+                .about("Timeout in seconds for each request. 0 means no timeout")
+        )
+        .arg(
+            Arg::new("retry")
+                .long("retry")
+                .takes_value(true)
+                .value_name("COUNT")
+                .default_value("2")
+                .about(
+                    "How many times a request is retried on temporary failures such as timeouts, \
+                    connection resets and status 429, 502, 503, 504. Retry-After header longer than 60 \
+                    seconds is shortened to 60 seconds",
+                )
+        )
+        .arg(
+            Arg::new("retry-backoff")
+                .long("retry-backoff")
+                .takes_value(true)
+                .value_name("MILLISECONDS")
+                .default_value("500")
+                .about(
+                    "Base delay in milliseconds of exponential backoff on retrying requests. \
+                    Retry-After header is honored when a server sends it",
+                )
+        )
+        .arg(
+            Arg::new("proxy")
+                .long("proxy")
+                .takes_value(true)
+                .value_name("URL")
+                .about(
+                    "Proxy URL such as \"http://proxy.example.com:8080\". By default the proxy is \
+                    taken from $http_proxy, $https_proxy and $all_proxy environment variables",
+                )
+        )
+        .arg(
+            Arg::new("no-proxy")
+                .long("no-proxy")
+                .takes_value(true)
+                .value_name("HOSTS")
+                .about(
+                    "Comma-separated hosts which are connected without the proxy. \"*\" disables the \
+                    proxy for all hosts. By default it is taken from $no_proxy environment variable",
+                )
+        )
+        .arg(
+            Arg::new("cacert")
+                .long("cacert")
+                .takes_value(true)
+                .value_name("FILE")
+                .about(
+                    "CA bundle file in PEM to verify server certificates instead of the system's one. \
+                    This is useful behind a TLS-intercepting proxy",
+                )
+        )
+        .arg(
+            Arg::new("cert")
+                .long("cert")
+                .takes_value(true)
+                .value_name("FILE")
+                .about(
+                    "Client certificate file in PEM for mutual TLS. The file must also contain the \
+                    private key unless --key is given",
+                )
+        )
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .takes_value(true)
+                .value_name("FILE")
+                .requires("cert")
+                .about("Private key file in PEM of the client certificate given by --cert")
+        )
+        .arg(
+            Arg::new("tls-min-version")
+                .long("tls-min-version")
+                .takes_value(true)
+                .value_name("VERSION")
+                .possible_values(["1.0", "1.1", "1.2", "1.3"])
+                .about("Minimum TLS version to connect to servers")
+        )
+        .arg(
+            Arg::new("insecure")
+                .short('k')
+                .long("insecure")
+                .about(
+                    "Do not verify TLS certificates of servers. This is insecure and connections can \
+                    be intercepted. Prefer --cacert",
+                )
+        )
+        .arg(
+            Arg::new("host-concurrency")
+                .long("host-concurrency")
+                .takes_value(true)
+                .value_name("COUNT")
+                .default_value("4")
+                .about("Maximum number of in-flight requests to each host. 0 means unlimited")
+        )
+        .arg(
+            Arg::new("host-rate")
+                .long("host-rate")
+                .takes_value(true)
+                .value_name("RPS")
+                .about("Maximum number of requests per second to each host. Unlimited by default")
+        )
+        .arg(
+            Arg::new("concurrency")
+                .long("concurrency")
+                .takes_value(true)
+                .value_name("COUNT")
+                .about("Maximum number of in-flight requests to all hosts. 0 means unlimited. Unlimited by default")
+        )
+        .arg(
+            Arg::new("host-limit")
+                .long("host-limit")
+                .takes_value(true)
+                .value_name("HOST=COUNT[:RPS]")
+                .multiple_occurrences(true)
+                .about(
+                    "Override --host-concurrency and --host-rate for the host. For example, \
+                    \"github.com=2:5\" allows 2 in-flight requests and 5 requests per second to \
+                    github.com. Either part can be empty and concurrency can be 0 to make it \
+                    unlimited. This option can be specified multiple times",
+                )
+        )
+        .arg(
+            Arg::new("header")
+                .short('H')
+                .long("header")
+                .takes_value(true)
+                .value_name("HEADER")
+                .multiple_occurrences(true)
+                .about(
+                    "Header sent with all requests in \"Name: value\" format. This option can be \
+                    specified multiple times. To send secrets only to specific hosts, use [[hosts]] \
+                    sections in the config file instead",
+                )
+        )
+        .arg(
+            Arg::new("user-agent")
+                .long("user-agent")
+                .takes_value(true)
+                .value_name("UA")
+                .about("Value of User-Agent header. \"fixred/{version}\" is sent by default")
+        )
+        .arg(
+            Arg::new("cache")
+                .long("cache")
+                .about(
+                    "Use persistent cache file to reuse resolved redirects across runs. The cache file \
+                    is $XDG_CACHE_HOME/fixred/cache.json (or ~/.cache/fixred/cache.json) by default",
+                )
+        )
+        .arg(
+            Arg::new("cache-file")
+                .long("cache-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .about("Path to the persistent cache file. This implies --cache")
+        )
+        .arg(
+            Arg::new("cache-ttl")
+                .long("cache-ttl")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .default_value("604800")
+                .about("How long redirected and not redirected URLs are cached in the cache file")
+        )
+        .arg(
+            Arg::new("cache-negative-ttl")
+                .long("cache-negative-ttl")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .default_value("86400")
+                .about("How long broken URLs are cached in the cache file")
+        )
+        .arg(
+            Arg::new("cache-list")
+                .long("cache-list")
+                .about("Output all entries in the cache file and exit")
+        )
+        .arg(
+            Arg::new("cache-prune")
+                .long("cache-prune")
+                .conflicts_with("cache-list")
+                .about("Remove expired entries from the cache file and exit")
+        )
+        .arg(
+            Arg::new("cache-clear")
+                .long("cache-clear")
+                .conflicts_with_all(&["cache-list", "cache-prune"])
+                .about("Remove all entries from the cache file and exit")
+        )
+        .arg(
+            Arg::new("mapping")
+                .long("mapping")
+                .takes_value(true)
+                .value_name("FILE")
+                .about(
+                    "Mapping file of old URLs to new URLs in CSV, TSV or JSON. Rules whose old URL \
+                    ends with '*' are prefix rules. URLs which are not in the mapping are resolved by \
+                    sending requests unless --offline is given",
+                )
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("FILE")
+                .about(
+                    "Config file in TOML. Rules to rewrite URLs with regular expressions can be \
+                    defined in [[rules]] sections. Rules are applied before the mapping file and \
+                    sending requests, and rewritten URLs are resolved further. Headers sent to \
+                    specific hosts can be defined in [[hosts]] sections",
+                )
+        )
+        .arg(
+            Arg::new("offline")
+                .long("offline")
+                .about(
+                    "Resolve redirects only with rules in the config file and the mapping file without \
+                    sending any request. This requires --config or --mapping option",
+                )
+        )
+        .arg(
+            Arg::new("export-mapping")
+                .long("export-mapping")
+                .takes_value(true)
+                .value_name("FILE")
+                .about(
+                    "Write all resolved URLs with their resolved URLs, statuses and timestamps to the \
+                    mapping file in CSV, TSV or JSON. The format is detected by the file extension. \
+                    The file can be read by --mapping option",
+                )
+        )
         .arg(
             Arg::new("extract")
                 .short('e')
@@ -54,6 +687,91 @@ fn main() -> Result<()> {
                 .value_name("REGEX")
                 .about("Fix URLs which are NOT matched to this pattern"),
         )
+        .arg(
+            Arg::new("markdown")
+                .long("markdown")
+                .takes_value(true)
+                .value_name("MODE")
+                .possible_values(["auto", "always", "never"])
+                .default_value("auto")
+                .about(
+                    "When to parse files as Markdown. \"auto\" parses files with extensions .md, \
+                    .markdown, .mdown, .mkd and .mkdn. \"always\" also parses stdin and other \
+                    files except HTML files and source files searched by --source. In Markdown, \
+                    URLs in code spans and code blocks are not fixed",
+                )
+        )
+        .arg(
+            Arg::new("markdown-code")
+                .long("markdown-code")
+                .about("Fix URLs in code spans and code blocks of Markdown files")
+        )
+        .arg(
+            Arg::new("source")
+                .long("source")
+                .takes_value(true)
+                .value_name("MODE")
+                .possible_values(["text", "comments", "strings"])
+                .default_value("text")
+                .about(
+                    "Which parts of source files (Rust, Go, JavaScript, TypeScript, C, C++, Java, \
+                    Kotlin, Scala, Swift, C#, Python, Ruby) are fixed. \"text\" fixes whole files, \
+                    \"comments\" fixes only comments and \"strings\" fixes comments and string \
+                    literals",
+                )
+        )
+        .arg(
+            Arg::new("dry-run")
+                .short('n')
+                .long("dry-run")
+                .about("Output redirects which would be fixed as FILE:LINE:COL: OLD -> NEW without modifying any file")
+        )
+        .arg(
+            Arg::new("diff")
+                .short('d')
+                .long("diff")
+                .conflicts_with("dry-run")
+                .about(
+                    "Output fixes as unified diff without modifying any file. The output can be \
+                    applied with `git apply`. This option requires file paths and cannot be used with stdin",
+                )
+        )
+        .arg(
+            Arg::new("check")
+                .short('c')
+                .long("check")
+                .conflicts_with("dry-run")
+                .about(
+                    "Check outdated links without modifying any file. Outdated links are output as \
+                    --dry-run does (or as --diff or --format does) and exit status is 1 when some of \
+                    them are found",
+                )
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(["json", "jsonl", "sarif"])
+                .conflicts_with_all(&["dry-run", "diff"])
+                .about(
+                    "Output a report of all links including their file paths, positions, filtered or \
+                    not, and how they were resolved in the format without modifying any file. \
+                    \"json\" outputs one JSON array and \"jsonl\" outputs JSON Lines. \"sarif\" \
+                    outputs only outdated links in SARIF with fixes",
+                )
+        )
+        .arg(
+            Arg::new("report-broken")
+                .short('b')
+                .long("report-broken")
+                .about(
+                    "Report broken links which answered 4xx or 5xx status or failed at the network \
+                    level to stderr at the end. They are grouped by files with line and column \
+                    numbers",
+                )
+        )
         .arg(
             Arg::new("PATH")
                 .about(
@@ -75,29 +793,106 @@ fn main() -> Result<()> {
 
     let start = log_enabled!(Level::Debug).then(time::Instant::now);
 
-    let red = CurlRedirector::default()
-        .extract(matches.value_of("extract").map(Regex::new).transpose()?)
-        .ignore(matches.value_of("ignore").map(Regex::new).transpose()?)
-        .shallow(matches.is_present("shallow"));
+    let cache = open_cache(&matches)?;
+    if let Some(cache) = &cache {
+        if matches.is_present("cache-list") {
+            let stdout = io::stdout();
+            print_cache_entries(stdout.lock(), cache)?;
+            return Ok(false);
+        }
+        if matches.is_present("cache-prune") {
+            let count = cache.prune();
+            cache.save()?;
+            eprintln!("Removed {} expired entries from {:?}", count, cache.path());
+            return Ok(false);
+        }
+        if matches.is_present("cache-clear") {
+            let count = cache.clear();
+            cache.save()?;
+            eprintln!("Removed {} entries from {:?}", count, cache.path());
+            return Ok(false);
+        }
+    }
 
-    if let Some(paths) = matches.values_of_os("PATH") {
-        info!("Processing all files in given paths via command line arguments");
-        let count = red.fix_all_files(paths)?;
-        info!("Processed {} files", count);
+    let config = match matches.value_of_os("config") {
+        Some(path) => Config::from_file(Path::new(path))?,
+        None => Config::default(),
+    };
+    let rules = config.rule_resolver()?;
+    let mapping = match matches.value_of_os("mapping") {
+        Some(path) => MappingResolver::from_file(Path::new(path))?,
+        None => MappingResolver::default(),
+    };
+    // Rules rewrite URLs first and the rewritten URLs are resolved further. The mapping wins over sending requests
+    if matches.is_present("offline") {
+        if !matches.is_present("config") && !matches.is_present("mapping") {
+            anyhow::bail!("--offline requires --config or --mapping option");
+        }
+        info!("Resolving redirects offline without sending any request");
+        let found = process(Rewrite::new(rules, mapping), &matches)?;
+        return Ok(found);
+    }
+
+    let allowed = if matches.is_present("only-permanent") {
+        // --allow-status has the default value so conflicts_with cannot detect it
+        if matches.occurrences_of("allow-status") > 0 {
+            anyhow::bail!("--only-permanent cannot be used with --allow-status");
+        }
+        PERMANENT_REDIRECTS.to_vec()
     } else {
-        info!("Fixing redirects in stdin");
-        let stdin = io::stdin();
-        let stdout = io::stdout();
-        let count = red
-            .fix(stdin.lock(), stdout.lock())
-            .context("While processing stdin")?;
-        info!("Fixed {} links in stdin", count);
+        parse_status_codes(matches.value_of("allow-status").unwrap())?
+    };
+    let method: Method = matches
+        .value_of("method")
+        .unwrap()
+        .parse()
+        .map_err(anyhow::Error::msg)?;
+    let resolver = CurlResolver::default()
+        .allow_status(allowed)
+        .method(method)
+        .connect_timeout(Duration::from_secs(parse_number(
+            &matches,
+            "connect-timeout",
+        )?))
+        .timeout(Duration::from_secs(parse_number(&matches, "timeout")?))
+        .retries(parse_number(&matches, "retry")?.try_into()?)
+        .retry_backoff(Duration::from_millis(parse_number(
+            &matches,
+            "retry-backoff",
+        )?))
+        .limiter(build_limiter(&matches)?)
+        .headers(build_headers(&matches, &config)?);
+    let resolver = match matches.value_of("user-agent") {
+        Some(ua) => resolver.user_agent(ua),
+        None => resolver,
+    };
+    let resolver = configure_tls(resolver, &matches)?;
+    let resolver = match &cache {
+        Some(cache) => resolver.disk_cache(cache.clone()),
+        None => resolver,
+    };
+    // Save resolutions in the cache even if processing failed halfway
+    let found = process(Rewrite::new(rules, Chain::new(mapping, resolver)), &matches);
+    if let Some(cache) = &cache {
+        cache.save()?;
     }
+    let found = found?;
 
     if let Some(start) = start {
         let secs = time::Instant::now().duration_since(start).as_secs_f32();
         debug!("Elapsed: {} seconds", secs);
     }
 
-    Ok(())
+    Ok(found)
+}
+
+fn main() {
+    match run() {
+        Ok(false) => {}
+        Ok(true) => process::exit(EXIT_OUTDATED_LINKS),
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            process::exit(EXIT_ERROR);
+        }
+    }
 }
@@ -0,0 +1,460 @@
+use crate::redirect::ResolvedUrl;
+use crate::resolve::{Outcome, Resolution, Resolver};
+use anyhow::{Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::iter::FromIterator;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+// Rules of mapping file ending with this character are prefix rules. For example,
+// "https://old.example.com/docs/*" -> "https://new.example.com/*" maps "https://old.example.com/docs/foo" to
+// "https://new.example.com/foo"
+const WILDCARD: char = '*';
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MappingFormat {
+    Csv,
+    Tsv,
+    Json,
+}
+
+impl MappingFormat {
+    // Detect the format of mapping file from its extension
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        match ext.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "tsv" => Ok(Self::Tsv),
+            "json" => Ok(Self::Json),
+            _ => anyhow::bail!(
+                "Format of mapping file {:?} is unknown. Its extension must be .csv, .tsv or .json",
+                path
+            ),
+        }
+    }
+}
+
+// One row of exported mapping file. Extra fields are ignored by MappingResolver
+#[derive(Serialize)]
+struct MappingRecord<'a> {
+    from: &'a str,
+    to: &'a str,
+    // Status of the first response. None when the URL was resolved without sending requests
+    status: Option<u32>,
+    // Unix time in seconds when the URL was resolved
+    resolved_at: u64,
+}
+
+// Write resolved URLs as a mapping file which MappingResolver can read. URLs which were not redirected are mapped to
+// themselves. URLs which failed to be resolved due to network errors are not written
+pub fn write_mapping<W: Write>(out: W, format: MappingFormat, urls: &[ResolvedUrl]) -> Result<()> {
+    let records = urls.iter().filter_map(|u| {
+        let to = match &u.resolution.outcome {
+            Outcome::Redirected(to) => to.as_str(),
+            Outcome::NotRedirected | Outcome::HttpError(_) => u.url.as_str(),
+            Outcome::NetworkError(..) | Outcome::Skipped => return None,
+        };
+        let resolved_at = u.resolved_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Some(MappingRecord {
+            from: &u.url,
+            to,
+            status: u.resolution.chain.first().map(|h| h.status),
+            resolved_at: resolved_at.as_secs(),
+        })
+    });
+
+    let delimiter = match format {
+        MappingFormat::Csv => b',',
+        MappingFormat::Tsv => b'\t',
+        MappingFormat::Json => {
+            let records: Vec<_> = records.collect();
+            let mut out = out;
+            serde_json::to_writer_pretty(&mut out, &records)?;
+            writeln!(out)?;
+            return Ok(out.flush()?);
+        }
+    };
+    let mut w = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(out);
+    for record in records {
+        w.serialize(record)?;
+    }
+    Ok(w.flush()?)
+}
+
+#[derive(Deserialize)]
+struct MappingEntry {
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MappingJson {
+    // {"https://old.example.com": "https://new.example.com", ...}
+    Object(HashMap<String, String>),
+    // [{"from": "https://old.example.com", "to": "https://new.example.com"}, ...]
+    Array(Vec<MappingEntry>),
+}
+
+// Resolver to resolve redirects with a mapping of old URLs to new URLs. It never sends any request so it can be used
+// offline. When shallow is not set, mappings are followed repeatedly
+#[derive(Default)]
+pub struct MappingResolver {
+    shallow: bool,
+    exact: HashMap<String, String>,
+    // Sorted by length of prefix in descending order so that the longest prefix wins
+    prefixes: Vec<(String, String)>,
+}
+
+impl MappingResolver {
+    // Load a mapping file. The format is detected by the file extension. .csv and .tsv files contain an old URL and a
+    // new URL in each row. Other columns are ignored. .json file contains an object of old URLs to new URLs, or an array
+    // of objects which have "from" and "to" fields
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let format = MappingFormat::from_path(path)?;
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read mapping file {:?}", path))?;
+        let res = match format {
+            MappingFormat::Csv => Self::from_csv(&content, b','),
+            MappingFormat::Tsv => Self::from_csv(&content, b'\t'),
+            MappingFormat::Json => Self::from_json(&content),
+        }
+        .with_context(|| format!("Could not parse mapping file {:?}", path))?;
+        debug!(
+            "Loaded {} mappings and {} prefix rules from {:?}",
+            res.exact.len(),
+            res.prefixes.len(),
+            path
+        );
+        Ok(res)
+    }
+
+    // Lines starting with '#' are comments. The first row is a header when its first column is not a URL
+    pub fn from_csv(content: &str, delimiter: u8) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(delimiter)
+            .comment(Some(b'#'))
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(content.as_bytes());
+        let mut mappings = vec![];
+        for (i, record) in reader.records().enumerate() {
+            let record = record?;
+            let from = record.get(0).unwrap_or("");
+            if i == 0 && !from.contains("://") {
+                continue;
+            }
+            let to = record.get(1).with_context(|| {
+                format!(
+                    "New URL is missing at line {}",
+                    record.position().map(|p| p.line()).unwrap_or(0)
+                )
+            })?;
+            mappings.push((from.to_string(), to.to_string()));
+        }
+        Ok(Self::from_iter(mappings))
+    }
+
+    pub fn from_json(content: &str) -> Result<Self> {
+        let mappings: Vec<_> = match serde_json::from_str(content)? {
+            MappingJson::Object(o) => o.into_iter().collect(),
+            MappingJson::Array(a) => a.into_iter().map(|e| (e.from, e.to)).collect(),
+        };
+        Ok(Self::from_iter(mappings))
+    }
+
+    fn map_once(&self, url: &str) -> Option<String> {
+        if let Some(to) = self.exact.get(url) {
+            return Some(to.clone());
+        }
+        for (from, to) in self.prefixes.iter() {
+            if let Some(rest) = url.strip_prefix(from.as_str()) {
+                return Some(match to.strip_suffix(WILDCARD) {
+                    Some(to) => format!("{}{}", to, rest),
+                    None => to.clone(),
+                });
+            }
+        }
+        None
+    }
+
+    fn map(&self, url: &str) -> Option<String> {
+        let mut current = self.map_once(url)?;
+        if self.shallow {
+            return Some(current);
+        }
+        // Guard against cyclic mappings
+        let mut seen = HashSet::new();
+        seen.insert(url.to_string());
+        while seen.insert(current.clone()) {
+            match self.map_once(&current) {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        Some(current)
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, S)> for MappingResolver {
+    fn from_iter<I: IntoIterator<Item = (S, S)>>(iter: I) -> Self {
+        let mut res = Self::default();
+        for (from, to) in iter {
+            let (from, to) = (from.into(), to.into());
+            match from.strip_suffix(WILDCARD) {
+                Some(prefix) => res.prefixes.push((prefix.to_string(), to)),
+                None => {
+                    res.exact.insert(from, to);
+                }
+            }
+        }
+        res.prefixes.sort_by_key(|p| Reverse(p.0.len()));
+        res
+    }
+}
+
+impl Resolver for MappingResolver {
+    fn shallow(&mut self, enabled: bool) {
+        self.shallow = enabled;
+    }
+
+    fn resolve(&self, url: &str) -> Resolution {
+        debug!("Resolving {} with mapping", url);
+        // https://datatracker.ietf.org/doc/html/rfc3986#section-3
+        let (mapped, fragment) = match self.map(url) {
+            Some(u) => (Some(u), None),
+            None => match url.split_once('#') {
+                Some((u, f)) => (self.map(u), Some(f)),
+                None => (None, None),
+            },
+        };
+        let outcome = match mapped {
+            Some(u) if u != url => match fragment {
+                Some(f) if !u.contains('#') => Outcome::Redirected(format!("{}#{}", u, f)),
+                _ => Outcome::Redirected(u),
+            },
+            _ => Outcome::NotRedirected,
+        };
+        Resolution::new(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::{Hop, NetworkErrorKind};
+    use crate::test_helper::{TestDir, TestDirEntry};
+    use std::time::Duration;
+
+    fn redirect(res: &MappingResolver, url: &str) -> Option<String> {
+        res.resolve(url).redirect().map(str::to_string)
+    }
+
+    #[test]
+    fn resolve_exact_and_prefix_mappings() {
+        let res: MappingResolver = vec![
+            ("https://a.example.com", "https://b.example.com"),
+            ("https://old.example.com/*", "https://new.example.com/*"),
+            (
+                "https://old.example.com/docs/*",
+                "https://docs.example.com/v2/*",
+            ),
+            ("https://gone.example.com/*", "https://example.com"),
+        ]
+        .into_iter()
+        .collect();
+
+        let tests = &[
+            ("https://a.example.com", Some("https://b.example.com")),
+            ("https://a.example.com/foo", None),
+            (
+                "https://a.example.com#top",
+                Some("https://b.example.com#top"),
+            ),
+            (
+                "https://old.example.com/foo/bar",
+                Some("https://new.example.com/foo/bar"),
+            ),
+            (
+                "https://old.example.com/docs/intro",
+                Some("https://docs.example.com/v2/intro"),
+            ),
+            ("https://gone.example.com/foo", Some("https://example.com")),
+            ("https://unknown.example.com", None),
+        ];
+        for (url, want) in tests {
+            assert_eq!(redirect(&res, url).as_deref(), *want, "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn follow_mappings_repeatedly() {
+        let mut res: MappingResolver = vec![
+            ("https://a.example.com", "https://b.example.com"),
+            ("https://b.example.com", "https://c.example.com"),
+            ("https://x.example.com", "https://y.example.com"),
+            ("https://y.example.com", "https://x.example.com"),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            redirect(&res, "https://a.example.com").as_deref(),
+            Some("https://c.example.com")
+        );
+        // Cyclic mappings
+        assert_eq!(redirect(&res, "https://x.example.com"), None);
+        assert_eq!(redirect(&res, "https://y.example.com"), None);
+
+        res.shallow(true);
+        assert_eq!(
+            redirect(&res, "https://a.example.com").as_deref(),
+            Some("https://b.example.com")
+        );
+    }
+
+    #[test]
+    fn load_mapping_files() {
+        let dir = TestDir::new(&[
+            TestDirEntry::File(
+                "mapping.csv",
+                "# comment\nfrom,to\nhttps://a.example.com, https://b.example.com,301\nhttps://c.example.com/*,https://d.example.com/*\n",
+            ),
+            TestDirEntry::File(
+                "mapping.tsv",
+                "https://a.example.com\thttps://b.example.com\nhttps://c.example.com/*\thttps://d.example.com/*\n",
+            ),
+            TestDirEntry::File(
+                "object.json",
+                r#"{"https://a.example.com": "https://b.example.com", "https://c.example.com/*": "https://d.example.com/*"}"#,
+            ),
+            TestDirEntry::File(
+                "array.json",
+                r#"[{"from": "https://a.example.com", "to": "https://b.example.com", "status": 301}, {"from": "https://c.example.com/*", "to": "https://d.example.com/*"}]"#,
+            ),
+            TestDirEntry::File("mapping.txt", "https://a.example.com https://b.example.com"),
+            TestDirEntry::File("broken.csv", "https://a.example.com\n"),
+            TestDirEntry::File("broken.json", "[1, 2, 3]"),
+        ])
+        .unwrap();
+
+        for file in &["mapping.csv", "mapping.tsv", "object.json", "array.json"] {
+            let res = MappingResolver::from_file(&dir.root.join(file)).unwrap();
+            assert_eq!(
+                redirect(&res, "https://a.example.com").as_deref(),
+                Some("https://b.example.com"),
+                "{}",
+                file
+            );
+            assert_eq!(
+                redirect(&res, "https://c.example.com/foo").as_deref(),
+                Some("https://d.example.com/foo"),
+                "{}",
+                file
+            );
+        }
+
+        for file in &["mapping.txt", "broken.csv", "broken.json", "missing.csv"] {
+            assert!(
+                MappingResolver::from_file(&dir.root.join(file)).is_err(),
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn export_and_load_mapping() {
+        let at = UNIX_EPOCH + Duration::from_secs(1000);
+        let resolved = |url: &str, outcome, status: Option<u32>| ResolvedUrl {
+            url: url.to_string(),
+            resolution: Resolution {
+                outcome,
+                chain: status
+                    .map(|status| Hop {
+                        url: url.to_string(),
+                        status,
+                        location: None,
+                    })
+                    .into_iter()
+                    .collect(),
+            },
+            resolved_at: at,
+        };
+        let urls = &[
+            resolved(
+                "https://a.example.com",
+                Outcome::Redirected("https://b.example.com".to_string()),
+                Some(301),
+            ),
+            resolved("https://c.example.com", Outcome::NotRedirected, Some(200)),
+            resolved("https://d.example.com", Outcome::HttpError(404), Some(404)),
+            resolved(
+                "https://e.example.com",
+                Outcome::Redirected("https://f.example.com".to_string()),
+                None,
+            ),
+            resolved(
+                "https://g.example.com",
+                Outcome::NetworkError(NetworkErrorKind::Dns, "oops".to_string()),
+                None,
+            ),
+        ];
+
+        let mut buf = vec![];
+        write_mapping(&mut buf, MappingFormat::Csv, urls).unwrap();
+        let csv = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            csv,
+            "from,to,status,resolved_at
+https://a.example.com,https://b.example.com,301,1000
+https://c.example.com,https://c.example.com,200,1000
+https://d.example.com,https://d.example.com,404,1000
+https://e.example.com,https://f.example.com,,1000
+"
+        );
+
+        let mut buf = vec![];
+        write_mapping(&mut buf, MappingFormat::Tsv, urls).unwrap();
+        let tsv = String::from_utf8(buf).unwrap();
+        assert_eq!(tsv, csv.replace(',', "\t"));
+
+        let mut buf = vec![];
+        write_mapping(&mut buf, MappingFormat::Json, urls).unwrap();
+        let json = String::from_utf8(buf).unwrap();
+        let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(v.as_array().unwrap().len(), 4);
+        assert_eq!(
+            v[0],
+            serde_json::json!({
+                "from": "https://a.example.com",
+                "to": "https://b.example.com",
+                "status": 301,
+                "resolved_at": 1000,
+            })
+        );
+        assert_eq!(v[3]["status"], serde_json::Value::Null);
+
+        for res in [
+            MappingResolver::from_csv(&csv, b',').unwrap(),
+            MappingResolver::from_csv(&tsv, b'\t').unwrap(),
+            MappingResolver::from_json(&json).unwrap(),
+        ] {
+            assert_eq!(
+                redirect(&res, "https://a.example.com").as_deref(),
+                Some("https://b.example.com")
+            );
+            assert_eq!(redirect(&res, "https://c.example.com"), None);
+            assert_eq!(redirect(&res, "https://d.example.com"), None);
+            assert_eq!(redirect(&res, "https://g.example.com"), None);
+        }
+    }
+}
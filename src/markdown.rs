@@ -0,0 +1,261 @@
+use crate::url::find_all_urls;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+// Extensions of Markdown files. Files with these extensions are parsed as Markdown by default
+const EXTENSIONS: &[&str] = &["md", "markdown", "mdown", "mkd", "mkdn"];
+
+pub fn is_markdown_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(e)))
+        .unwrap_or(false)
+}
+
+// When files are parsed as Markdown
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MarkdownMode {
+    // Only files whose extensions are Markdown's. Stdin is not parsed as Markdown
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl MarkdownMode {
+    pub fn is_enabled(self, path: Option<&Path>) -> bool {
+        match self {
+            Self::Auto => path.map(is_markdown_file).unwrap_or(false),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+impl FromStr for MarkdownMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!(
+                "Unknown Markdown mode {:?}. It must be one of \"auto\", \"always\", \"never\"",
+                s
+            )),
+        }
+    }
+}
+
+fn is_http_url(s: &str) -> bool {
+    s.starts_with("https://") || s.starts_with("http://")
+}
+
+// Find the span of the link destination in the range. The destination follows '(' of inline link or ':' of reference
+// definition, optionally with '<' and spaces. None is returned when the destination is not written as-is in the source
+// (e.g. it contains backslash escapes)
+fn find_destination(content: &str, range: Range<usize>, dest: &str) -> Option<(usize, usize)> {
+    let src = &content[range.clone()];
+    src.match_indices(dest)
+        .find(|(i, _)| {
+            let before = src[..*i].trim_end_matches(|c: char| c.is_ascii_whitespace() || c == '<');
+            before.ends_with(['(', ':'])
+        })
+        .map(|(i, _)| (range.start + i, range.start + i + dest.len()))
+}
+
+fn overlaps(a: (usize, usize), b: &Range<usize>) -> bool {
+    a.0 < b.end && b.start < a.1
+}
+
+// Find all URLs in Markdown document. Destinations of inline links, images and reference definitions are found by
+// parsing the document as CommonMark. Other URLs such as autolinks and bare URLs are found in text and inline HTML in
+// the same way as find_all_urls. URLs in code spans and code blocks are ignored unless include_code is true since they
+// are often example outputs which should be kept verbatim
+pub fn find_markdown_urls(content: &str, include_code: bool) -> Vec<(usize, usize)> {
+    let parser = Parser::new_ext(content, Options::all());
+    let mut dests = vec![];
+    for (_, def) in parser.reference_definitions().iter() {
+        if is_http_url(&def.dest) {
+            dests.extend(find_destination(content, def.span.clone(), &def.dest));
+        }
+    }
+
+    // Ranges of text where bare URLs are searched. Contiguous ranges are merged since a URL in text may be split into
+    // multiple events
+    let mut texts: Vec<Range<usize>> = vec![];
+    let mut in_code_block = false;
+    // End of the last event in the text of each link or image being parsed. Links can be nested like [![alt](img)](url)
+    let mut text_ends: Vec<usize> = vec![];
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::Link(..)) | Event::Start(Tag::Image(..)) => {
+                text_ends.push(range.start);
+                continue;
+            }
+            Event::End(Tag::Link(_, dest, _)) | Event::End(Tag::Image(_, dest, _)) => {
+                let text_end = text_ends.pop().unwrap_or(range.start);
+                // Search the destination after the link text since the text may contain the same URL
+                if is_http_url(&dest) {
+                    dests.extend(find_destination(content, text_end..range.end, &dest));
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(_) | Event::Code(_) | Event::Html(_) => {
+                let is_code = in_code_block || matches!(event, Event::Code(_));
+                if include_code || !is_code {
+                    match texts.last_mut() {
+                        Some(last) if last.end == range.start => last.end = range.end,
+                        _ => texts.push(range.clone()),
+                    }
+                }
+            }
+            _ => {}
+        }
+        if let Some(end) = text_ends.last_mut() {
+            *end = (*end).max(range.end);
+        }
+    }
+
+    let mut urls: Vec<_> = texts
+        .into_iter()
+        .flat_map(|r| {
+            find_all_urls(&content[r.clone()])
+                .into_iter()
+                .map(move |(s, e)| (r.start + s, r.start + e))
+        })
+        .filter(|&(s, e)| !dests.iter().any(|&d| overlaps(d, &(s..e))))
+        .collect();
+    urls.extend(dests);
+    urls.sort_unstable();
+    urls.dedup();
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_urls(content: &str, include_code: bool) -> Vec<&str> {
+        find_markdown_urls(content, include_code)
+            .into_iter()
+            .map(|(s, e)| &content[s..e])
+            .collect()
+    }
+
+    #[test]
+    fn find_urls_in_markdown() {
+        let tests: &[(&str, &[&str])] = &[
+            ("bare https://example.com url", &["https://example.com"]),
+            ("autolink <https://example.com>", &["https://example.com"]),
+            ("[link](https://example.com)", &["https://example.com"]),
+            (
+                "[link](https://example.com \"title\")",
+                &["https://example.com"],
+            ),
+            ("[link](<https://example.com>)", &["https://example.com"]),
+            (
+                "[link](https://en.wikipedia.org/wiki/Rust_(programming_language))",
+                &["https://en.wikipedia.org/wiki/Rust_(programming_language)"],
+            ),
+            (
+                "[link](https://en.wikipedia.org/wiki/Rust_(programming_language) \"title\")",
+                &["https://en.wikipedia.org/wiki/Rust_(programming_language)"],
+            ),
+            (
+                "see https://en.wikipedia.org/wiki/Rust_(programming_language).",
+                &["https://en.wikipedia.org/wiki/Rust_(programming_language)"],
+            ),
+            (
+                "(see https://en.wikipedia.org/wiki/Rust_(programming_language))",
+                &["https://en.wikipedia.org/wiki/Rust_(programming_language)"],
+            ),
+            (
+                "[https://example.com/a_(b)](https://example.com/c_(d))",
+                &["https://example.com/a_(b)", "https://example.com/c_(d)"],
+            ),
+            (
+                "![image](https://example.com/a.png)",
+                &["https://example.com/a.png"],
+            ),
+            (
+                "[https://example.com](https://example.com)",
+                &["https://example.com", "https://example.com"],
+            ),
+            (
+                "[![badge](https://example.com/badge.svg)](https://example.com/ci)",
+                &["https://example.com/badge.svg", "https://example.com/ci"],
+            ),
+            (
+                "[link][ref]\n\n[ref]: https://example.com/ref 'title'\n",
+                &["https://example.com/ref"],
+            ),
+            (
+                "[link](./relative/path.md) and [mail](mailto:foo@example.com)",
+                &[],
+            ),
+            (
+                "text https://example.com/foo_bar_baz&a=1 text",
+                &["https://example.com/foo_bar_baz&a=1"],
+            ),
+            (
+                "<a href=\"https://example.com/html\">html</a>",
+                &["https://example.com/html"],
+            ),
+            (
+                "| a | b |\n|---|---|\n| https://example.com/table | c |\n",
+                &["https://example.com/table"],
+            ),
+            ("`https://example.com/code`", &[]),
+            (
+                "```\ncurl https://example.com/block\n```\n\nafter https://example.com",
+                &["https://example.com"],
+            ),
+            ("    https://example.com/indented\n", &[]),
+        ];
+        for (input, want) in tests {
+            assert_eq!(&find_urls(input, false), want, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn find_urls_in_code() {
+        let input = "`https://example.com/a`\n\n```sh\ncurl https://example.com/b\n```\n";
+        assert_eq!(
+            find_urls(input, true),
+            &["https://example.com/a", "https://example.com/b"]
+        );
+    }
+
+    #[test]
+    fn markdown_mode() {
+        assert_eq!("auto".parse(), Ok(MarkdownMode::Auto));
+        assert_eq!("always".parse(), Ok(MarkdownMode::Always));
+        assert_eq!("never".parse(), Ok(MarkdownMode::Never));
+        assert!("yes".parse::<MarkdownMode>().is_err());
+
+        let md = Some(Path::new("README.md"));
+        let txt = Some(Path::new("README.txt"));
+        assert!(MarkdownMode::Auto.is_enabled(md));
+        assert!(!MarkdownMode::Auto.is_enabled(txt));
+        assert!(!MarkdownMode::Auto.is_enabled(None));
+        assert!(MarkdownMode::Always.is_enabled(None));
+        assert!(!MarkdownMode::Never.is_enabled(md));
+    }
+
+    #[test]
+    fn detect_markdown_file() {
+        for (path, want) in &[
+            ("README.md", true),
+            ("docs/guide.MARKDOWN", true),
+            ("README.txt", false),
+            ("md", false),
+        ] {
+            assert_eq!(is_markdown_file(Path::new(path)), *want, "{:?}", path);
+        }
+    }
+}
@@ -1,24 +1,184 @@
-use crate::replace::{replace_all, Replacement};
-use crate::resolve::{CurlResolver, Resolver};
-use crate::url::find_all_urls;
+use crate::extract::{Extractor, Extractors, FilePattern, Span};
+use crate::markdown::MarkdownMode;
+use crate::replace::{line_col, replace_all, Replacement};
+use crate::resolve::{CurlResolver, Hop, Outcome, Resolution, Resolver};
+use crate::source::SourceMode;
+use crate::url::UrlContext;
 use anyhow::{Context, Result};
 use log::{debug, info, warn};
 use rayon::prelude::*;
 use regex::Regex;
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::io::{BufWriter, Read, Write};
-use std::path::Path;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
 use walkdir::WalkDir;
 
+fn walk_files<'a>(
+    paths: impl Iterator<Item = &'a OsStr>,
+    mut f: impl FnMut(PathBuf) -> Result<()>,
+) -> Result<usize> {
+    paths
+        .flat_map(WalkDir::new)
+        .filter(|entry| match entry {
+            Ok(e) => e.file_type().is_file(),
+            Err(_) => true,
+        })
+        .map(|entry| {
+            f(entry?.into_path())?;
+            Ok(1)
+        })
+        .sum()
+}
+
+fn read_utf8_file(file: &Path) -> Option<String> {
+    match fs::read_to_string(file) {
+        Err(err) => {
+            warn!("Ignored non-UTF8 file {:?}: {}", file, err);
+            None
+        }
+        Ok(s) => Some(s),
+    }
+}
+
+pub struct Link {
+    pub start: usize,
+    pub end: usize,
+    pub outcome: Outcome,
+    // Requests sent while resolving the URL. Empty when the URL was skipped
+    pub chain: Vec<Hop>,
+    // Text at start..end is the URL encoded for this context
+    pub context: UrlContext,
+}
+
+impl Link {
+    pub fn replacement(&self) -> Option<Replacement> {
+        if let Outcome::Redirected(url) = &self.outcome {
+            Some(Replacement::new(
+                self.start,
+                self.end,
+                self.context.encode(url),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+// All links found in a file
+pub struct FileLinks {
+    pub path: PathBuf,
+    pub content: String,
+    pub links: Vec<Link>,
+}
+
+// Replacements planned for a file. The file is not modified
+pub struct FileReplacements {
+    pub path: PathBuf,
+    pub content: String,
+    pub replacements: Vec<Replacement>,
+}
+
+// Link which answered with 4xx or 5xx status or failed at the network level
+#[derive(Clone, Debug)]
+pub struct BrokenLink {
+    // None when the link was not in a file such as stdin
+    pub path: Option<PathBuf>,
+    pub start: usize,
+    pub line: usize,
+    pub column: usize,
+    pub url: String,
+    pub outcome: Outcome,
+}
+
+// Outcomes of all links resolved by Redirector. Broken links are collected to report them at the end of a run
+#[derive(Default, Clone, Debug)]
+pub struct Summary {
+    pub redirected: usize,
+    pub not_redirected: usize,
+    pub skipped: usize,
+    pub broken: Vec<BrokenLink>,
+}
+
+impl Summary {
+    pub fn total(&self) -> usize {
+        self.redirected + self.not_redirected + self.skipped + self.broken.len()
+    }
+
+    // Broken links grouped by their files in order of processing. Links in each file are sorted by their positions
+    pub fn broken_by_file(&self) -> Vec<(Option<&Path>, Vec<&BrokenLink>)> {
+        let mut groups: Vec<(Option<&Path>, Vec<&BrokenLink>)> = vec![];
+        for link in self.broken.iter() {
+            let path = link.path.as_deref();
+            match groups.iter_mut().find(|(p, _)| *p == path) {
+                Some((_, links)) => links.push(link),
+                None => groups.push((path, vec![link])),
+            }
+        }
+        for (_, links) in groups.iter_mut() {
+            links.sort_by_key(|l| l.start);
+        }
+        groups
+    }
+}
+
+// URL resolved by Redirector with the time when it was resolved
+#[derive(Clone, Debug)]
+pub struct ResolvedUrl {
+    pub url: String,
+    pub resolution: Resolution,
+    pub resolved_at: SystemTime,
+}
+
 #[derive(Default)]
 pub struct Redirector<R: Resolver> {
     extract: Option<Regex>,
     ignore: Option<Regex>,
+    extractors: Extractors,
     resolver: R,
+    summary: Mutex<Summary>,
+    // Resolved URLs are recorded only when this is Some
+    resolved: Option<Mutex<HashMap<String, ResolvedUrl>>>,
 }
 
 impl<R: Resolver> Redirector<R> {
+    pub fn new(resolver: R) -> Self {
+        Self {
+            extract: None,
+            ignore: None,
+            extractors: Extractors::default(),
+            resolver,
+            summary: Mutex::new(Summary::default()),
+            resolved: None,
+        }
+    }
+
+    // Summary of all links resolved by this instance so far
+    pub fn summary(&self) -> Summary {
+        self.summary.lock().unwrap().clone()
+    }
+
+    // Record all resolved URLs to get them later by resolved_urls(). Skipped URLs are not recorded
+    pub fn record_resolved(mut self, enabled: bool) -> Self {
+        debug!("Record resolved URLs?: {}", enabled);
+        self.resolved = enabled.then(Mutex::default);
+        self
+    }
+
+    // All URLs resolved by this instance so far sorted by URL. This is empty unless record_resolved() is enabled
+    pub fn resolved_urls(&self) -> Vec<ResolvedUrl> {
+        let mut urls: Vec<_> = match &self.resolved {
+            Some(m) => m.lock().unwrap().values().cloned().collect(),
+            None => vec![],
+        };
+        urls.sort_by(|a, b| a.url.cmp(&b.url));
+        urls
+    }
+
     pub fn extract(mut self, pattern: Option<Regex>) -> Self {
         debug!("Regex to extract URLs: {:?}", pattern);
         self.extract = pattern;
@@ -31,6 +191,41 @@ impl<R: Resolver> Redirector<R> {
         self
     }
 
+    // Set which files are parsed as Markdown. By default files with Markdown extensions such as .md are parsed
+    pub fn markdown(mut self, mode: MarkdownMode) -> Self {
+        debug!("Markdown mode: {:?}", mode);
+        self.extractors = mem::take(&mut self.extractors).markdown(mode);
+        self
+    }
+
+    // Fix URLs in code spans and code blocks of Markdown files. By default they are kept verbatim
+    pub fn markdown_code(mut self, enabled: bool) -> Self {
+        debug!("Fix URLs in Markdown code?: {}", enabled);
+        self.extractors = mem::take(&mut self.extractors).markdown_code(enabled);
+        self
+    }
+
+    // Set which parts of source files are searched for URLs. For example, SourceMode::Comments fixes URLs only in
+    // comments of .rs files and keeps URLs in string literals
+    pub fn source(mut self, mode: SourceMode) -> Self {
+        debug!("Source mode: {:?}", mode);
+        self.extractors = mem::take(&mut self.extractors).source(mode);
+        self
+    }
+
+    // Set extractors to find URLs in files. This overrides markdown(), markdown_code() and source() called before
+    pub fn extractors(mut self, extractors: Extractors) -> Self {
+        self.extractors = extractors;
+        self
+    }
+
+    // Find URLs in files matched to the pattern with the extractor
+    pub fn extractor(mut self, pattern: FilePattern, extractor: impl Extractor + 'static) -> Self {
+        debug!("Extractor for files: {:?}", pattern);
+        self.extractors = mem::take(&mut self.extractors).register(pattern, extractor);
+        self
+    }
+
     pub fn shallow(mut self, enabled: bool) -> Self {
         debug!("Shallow redirect?: {}", enabled);
         self.resolver.shallow(enabled);
@@ -51,20 +246,96 @@ impl<R: Resolver> Redirector<R> {
         true
     }
 
-    fn find_replacements(&self, content: &str) -> Vec<Replacement> {
-        let spans = find_all_urls(content); // Collect to Vec to use par_iter which is more efficient than par_bridge
+    pub fn find_links(&self, content: &str) -> Vec<Link> {
+        self.resolve_links(content, None)
+    }
+
+    fn resolve_links(&self, content: &str, path: Option<&Path>) -> Vec<Link> {
+        // Collect to Vec to use par_iter which is more efficient than par_bridge
+        let spans = self.extractors.extract(content, path);
         debug!("Found {} links", spans.len());
-        let replacements = spans
+        spans
             .into_par_iter()
-            .filter_map(|(start, end)| {
-                let url = &content[start..end];
-                if !self.should_resolve(url) {
+            .map(|span| {
+                let Span {
+                    start,
+                    end,
+                    context,
+                } = span;
+                let url = context.decode(&content[start..end]);
+                let url = url.as_ref();
+                let res = if self.should_resolve(url) {
+                    let res = self.resolver.resolve(url);
+                    if let Some(m) = &self.resolved {
+                        let mut resolved = m.lock().unwrap();
+                        if !resolved.contains_key(url) {
+                            let r = ResolvedUrl {
+                                url: url.to_string(),
+                                resolution: res.clone(),
+                                resolved_at: SystemTime::now(),
+                            };
+                            resolved.insert(url.to_string(), r);
+                        }
+                    }
+                    res
+                } else {
                     debug!("Skipped URL: {}", url);
-                    return None;
+                    Resolution::new(Outcome::Skipped)
+                };
+
+                match &res.outcome {
+                    // Chain is empty when the URL was resolved without sending requests
+                    Outcome::Redirected(to) if res.chain.is_empty() => {
+                        info!("Redirect {} -> {}", url, to)
+                    }
+                    Outcome::Redirected(to) => {
+                        info!("Redirect {} -> {} via {}", url, to, res.chain_display())
+                    }
+                    Outcome::NetworkError(..) => {
+                        warn!("Could not resolve {:?}: {}", url, res.outcome)
+                    }
+                    _ => {}
+                }
+                {
+                    let mut summary = self.summary.lock().unwrap();
+                    match &res.outcome {
+                        Outcome::Redirected(_) => summary.redirected += 1,
+                        Outcome::NotRedirected => summary.not_redirected += 1,
+                        Outcome::Skipped => summary.skipped += 1,
+                        Outcome::HttpError(_) | Outcome::NetworkError(..) => {
+                            let (line, column) = line_col(content, start);
+                            summary.broken.push(BrokenLink {
+                                path: path.map(Path::to_path_buf),
+                                start,
+                                line,
+                                column,
+                                url: url.to_string(),
+                                outcome: res.outcome.clone(),
+                            });
+                        }
+                    }
+                }
+
+                Link {
+                    start,
+                    end,
+                    outcome: res.outcome,
+                    chain: res.chain,
+                    context,
                 }
-                let url = self.resolver.resolve(url);
-                url.map(|text| Replacement { start, end, text })
             })
+            .collect()
+    }
+
+    pub fn find_replacements(&self, content: &str) -> Vec<Replacement> {
+        self.resolve_replacements(content, None)
+    }
+
+    fn resolve_replacements(&self, content: &str, path: Option<&Path>) -> Vec<Replacement> {
+        let replacements = self
+            .resolve_links(content, path)
+            .iter()
+            .filter_map(Link::replacement)
             .collect::<Vec<_>>(); // Collect to Vec to check errors before overwriting files
         debug!("Found {} redirects", replacements.len());
         replacements
@@ -73,19 +344,16 @@ impl<R: Resolver> Redirector<R> {
     pub fn fix_file(&self, file: &Path) -> Result<()> {
         info!("Fixing redirects in {:?}", &file);
 
-        let content = match fs::read_to_string(&file) {
-            Err(err) => {
-                warn!("Ignored non-UTF8 file {:?}: {}", &file, err);
-                return Ok(());
-            }
-            Ok(s) => s,
+        let content = match read_utf8_file(file) {
+            Some(s) => s,
+            None => return Ok(()),
         };
-        let replacements = self.find_replacements(&content);
+        let replacements = self.resolve_replacements(&content, Some(file));
         if replacements.is_empty() {
             info!("Fixed no link in {:?} (skipped overwriting)", &file);
             return Ok(());
         }
-        let mut out = BufWriter::new(fs::File::create(&file)?); // Truncate the file after all replacements are collected without error
+        let mut out = BufWriter::new(fs::File::create(file)?); // Truncate the file after all replacements are collected without error
         replace_all(&mut out, &content, &replacements)?;
 
         info!("Fixed {} links in {:?}", replacements.len(), &file);
@@ -93,19 +361,53 @@ impl<R: Resolver> Redirector<R> {
     }
 
     pub fn fix_all_files<'a>(&self, paths: impl Iterator<Item = &'a OsStr>) -> Result<usize> {
-        paths
-            .flat_map(WalkDir::new)
-            .filter(|entry| match entry {
-                Ok(e) => e.file_type().is_file(),
-                Err(_) => true,
-            })
-            .map(|entry| {
-                let path = entry?.into_path();
-                self.fix_file(&path)
-                    .with_context(|| format!("While processing {:?}", &path))?;
-                Ok(1)
+        walk_files(paths, |path| {
+            self.fix_file(&path)
+                .with_context(|| format!("While processing {:?}", &path))
+        })
+    }
+
+    // Find all redirects in files without modifying them. Only files which would be fixed are returned
+    pub fn find_all_replacements<'a>(
+        &self,
+        paths: impl Iterator<Item = &'a OsStr>,
+    ) -> Result<Vec<FileReplacements>> {
+        let all = self
+            .find_all_links(paths)?
+            .into_iter()
+            .filter_map(|file| {
+                let replacements: Vec<_> =
+                    file.links.iter().filter_map(Link::replacement).collect();
+                info!("Found {} redirects in {:?}", replacements.len(), &file.path);
+                (!replacements.is_empty()).then_some(FileReplacements {
+                    path: file.path,
+                    content: file.content,
+                    replacements,
+                })
             })
-            .sum()
+            .collect();
+        Ok(all)
+    }
+
+    // Find all links in files and resolve them without modifying the files
+    pub fn find_all_links<'a>(
+        &self,
+        paths: impl Iterator<Item = &'a OsStr>,
+    ) -> Result<Vec<FileLinks>> {
+        let mut all = vec![];
+        walk_files(paths, |path| {
+            info!("Finding links in {:?}", &path);
+            if let Some(content) = read_utf8_file(&path) {
+                let links = self.resolve_links(&content, Some(&path));
+                all.push(FileLinks {
+                    path,
+                    content,
+                    links,
+                });
+            }
+            Ok(())
+        })?;
+        Ok(all)
     }
 
     pub fn fix<T: Read, U: Write>(&self, mut input: T, output: U) -> Result<usize> {
@@ -123,6 +425,7 @@ pub type CurlRedirector = Redirector<CurlResolver>;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::resolve::NetworkErrorKind;
     use crate::test_helper::*;
     use std::iter;
     use std::path::PathBuf;
@@ -175,6 +478,192 @@ mod tests {
     }
 
     #[test]
+    fn find_all_replacements_without_fixing() {
+        let entries = &[
+            TestDirEntry::File(
+                "test1.txt",
+                "https://foo1.example.com\nhttps://example.com/foo1\nhttps://example.com\n",
+            ),
+            TestDirEntry::File("test2.txt", "https://example.com\n"),
+            TestDirEntry::Binary("test.bin", b"\xf0\x28\x8c\xbc"),
+        ];
+
+        let dir = TestDir::new(entries).unwrap();
+
+        let red = TestRedirector::default();
+        let found = red
+            .find_all_replacements(iter::once(dir.root.as_ref()))
+            .unwrap();
+        assert_eq!(found.len(), 1);
+
+        let found = &found[0];
+        assert_eq!(found.path, dir.root.join("test1.txt"));
+        let have: Vec<_> = found
+            .replacements
+            .iter()
+            .map(|r| (&found.content[r.start..r.end], r.text.as_str()))
+            .collect();
+        assert_eq!(
+            have,
+            &[
+                ("https://foo1.example.com", "https://piyo1.example.com"),
+                ("https://example.com/foo1", "https://example.com/piyo1"),
+            ]
+        );
+
+        // Files are not modified
+        assert_files(&dir.files);
+    }
+
+    #[test]
+    fn find_all_links_with_outcomes() {
+        let entries = &[TestDirEntry::File(
+            "test.txt",
+            "https://foo.example.com https://example.com https://error.example.com https://foo.github.com",
+        )];
+        let dir = TestDir::new(entries).unwrap();
+
+        let pat = Regex::new("github\\.com").unwrap();
+        let red = TestRedirector::default().ignore(Some(pat));
+        let found = red.find_all_links(iter::once(dir.root.as_ref())).unwrap();
+        assert_eq!(found.len(), 1);
+
+        let found = &found[0];
+        let have: Vec<_> = found
+            .links
+            .iter()
+            .map(|l| (&found.content[l.start..l.end], l.outcome.clone()))
+            .collect();
+        let chains: Vec<_> = found
+            .links
+            .iter()
+            .map(|l| l.chain.iter().map(|h| h.status).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(
+            have,
+            &[
+                (
+                    "https://foo.example.com",
+                    Outcome::Redirected("https://piyo.example.com".to_string()),
+                ),
+                ("https://example.com", Outcome::NotRedirected),
+                (
+                    "https://error.example.com",
+                    Outcome::NetworkError(
+                        NetworkErrorKind::Other,
+                        "could not resolve https://error.example.com".to_string()
+                    ),
+                ),
+                ("https://foo.github.com", Outcome::Skipped),
+            ]
+        );
+        let want: &[Vec<u32>] = &[vec![301, 200], vec![200], vec![], vec![]];
+        assert_eq!(chains, want);
+
+        let summary = red.summary();
+        assert_eq!(summary.total(), 4);
+        assert_eq!(summary.redirected, 1);
+        assert_eq!(summary.not_redirected, 1);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.broken.len(), 1);
+        let broken = &summary.broken[0];
+        assert_eq!(broken.url, "https://error.example.com");
+        assert_eq!(
+            broken.path.as_deref(),
+            Some(dir.root.join("test.txt").as_path())
+        );
+        assert_eq!((broken.line, broken.column), (1, 45));
+        assert_files(&dir.files);
+    }
+
+    #[test]
+    fn record_resolved_urls() {
+        let input = "https://foo.example.com https://example.com https://foo.example.com https://foo.github.com";
+
+        let red = TestRedirector::default();
+        red.find_links(input);
+        assert!(red.resolved_urls().is_empty());
+
+        let pat = Regex::new("github\\.com").unwrap();
+        let red = TestRedirector::default()
+            .ignore(Some(pat))
+            .record_resolved(true);
+        red.find_links(input);
+        let have: Vec<_> = red
+            .resolved_urls()
+            .into_iter()
+            .map(|r| (r.url, r.resolution.outcome))
+            .collect();
+        assert_eq!(
+            have,
+            &[
+                ("https://example.com".to_string(), Outcome::NotRedirected),
+                (
+                    "https://foo.example.com".to_string(),
+                    Outcome::Redirected("https://piyo.example.com".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn collect_broken_links_while_fixing() {
+        let entries = &[
+            TestDirEntry::File(
+                "test1.txt",
+                "https://foo.example.com\n  https://notfound.example.com\nhttps://error.example.com\n",
+            ),
+            TestDirEntry::File("test2.txt", "https://example.com/notfound\n"),
+            TestDirEntry::File("test3.txt", "https://example.com\n"),
+        ];
+        let dir = TestDir::new(entries).unwrap();
+
+        let red = TestRedirector::default();
+        let paths = &[
+            dir.root.join("test1.txt"),
+            dir.root.join("test2.txt"),
+            dir.root.join("test3.txt"),
+        ];
+        red.fix_all_files(paths.iter().map(|p| p.as_ref())).unwrap();
+
+        let summary = red.summary();
+        let have: Vec<_> = summary
+            .broken_by_file()
+            .into_iter()
+            .map(|(path, links)| {
+                let links: Vec<_> = links
+                    .iter()
+                    .map(|l| (l.line, l.column, l.url.as_str(), l.outcome.is_broken()))
+                    .collect();
+                (path.unwrap().to_path_buf(), links)
+            })
+            .collect();
+        let want = vec![
+            (
+                paths[0].clone(),
+                vec![
+                    (2, 3, "https://notfound.example.com", true),
+                    (3, 1, "https://error.example.com", true),
+                ],
+            ),
+            (
+                paths[1].clone(),
+                vec![(1, 1, "https://example.com/notfound", true)],
+            ),
+        ];
+        assert_eq!(have, want);
+
+        // Links are fixed in the same crawl
+        let content = fs::read_to_string(&paths[0]).unwrap();
+        assert!(
+            content.starts_with("https://piyo.example.com\n"),
+            "{:?}",
+            content
+        );
+    }
+
+    #[test]
+    #[allow(invalid_from_utf8)]
     fn ignore_non_utf8_file() {
         // Invalid UTF-8 sequence
         let content = b"\xf0\x28\x8c\xbc";
@@ -199,6 +688,166 @@ mod tests {
         red.fix_all_files(iter::once(p.as_ref())).unwrap_err();
     }
 
+    #[test]
+    fn fix_markdown_files() {
+        let content = "[link](https://example.com/foo1) and `https://example.com/foo2`\n\n```\nhttps://example.com/foo3\n```\n";
+        let entries = &[
+            TestDirEntry::File("test.md", content),
+            TestDirEntry::File("test.txt", content),
+        ];
+        let dir = TestDir::new(entries).unwrap();
+        let md = dir.root.join("test.md");
+        let txt = dir.root.join("test.txt");
+        let paths = [md.as_os_str(), txt.as_os_str()];
+
+        let red = TestRedirector::default();
+        red.fix_all_files(paths.iter().copied()).unwrap();
+        assert_files(&[
+            (md.clone(), content.replacen("foo1", "piyo1", 1)),
+            (txt.clone(), content.replace("foo", "piyo")),
+        ]);
+
+        let dir = TestDir::new(entries).unwrap();
+        let md = dir.root.join("test.md");
+        let red = TestRedirector::default().markdown_code(true);
+        red.fix_file(&md).unwrap();
+        assert_files(&[(md.clone(), content.replace("foo", "piyo"))]);
+
+        let dir = TestDir::new(entries).unwrap();
+        let md = dir.root.join("test.md");
+        let red = TestRedirector::default().markdown(MarkdownMode::Never);
+        red.fix_file(&md).unwrap();
+        assert_files(&[(md, content.replace("foo", "piyo"))]);
+
+        // Stdin is parsed as Markdown only when it is enabled explicitly
+        let red = TestRedirector::default().markdown(MarkdownMode::Always);
+        let mut output = vec![];
+        red.fix(content.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            content.replacen("foo1", "piyo1", 1)
+        );
+    }
+
+    #[test]
+    fn fix_html_files() {
+        let content = r#"<a href="https://example.com/foo1?a=1&amp;b=2">https://example.com/foo2</a>
+<img srcset="https://example.com/foo3.png 1x, https://example.com/foo4.png 2x">
+<meta http-equiv="refresh" content="0; url=https://foo.example.com/?q=a&amp;r=b">
+"#;
+        let entries = &[TestDirEntry::File("index.html", content)];
+        let dir = TestDir::new(entries).unwrap();
+        let path = dir.root.join("index.html");
+
+        let red = TestRedirector::default().record_resolved(true);
+        red.fix_file(&path).unwrap();
+        let want = content
+            .replacen("foo1", "piyo1", 1)
+            .replace("foo2", "piyo2")
+            .replace("foo3", "piyo3")
+            .replace("foo4", "piyo4")
+            .replace("foo.example.com", "piyo.example.com");
+        assert_files(&[(path, want)]);
+
+        // Entities are decoded before resolving URLs
+        let urls: Vec<_> = red.resolved_urls().into_iter().map(|r| r.url).collect();
+        assert!(
+            urls.contains(&"https://example.com/foo1?a=1&b=2".to_string()),
+            "{:?}",
+            urls
+        );
+    }
+
+    #[test]
+    fn fix_only_comments_in_source_files() {
+        let content =
+            "// https://example.com/foo1\nconst URL: &str = \"https://example.com/foo2\";\n";
+        let entries = &[TestDirEntry::File("main.rs", content)];
+        let dir = TestDir::new(entries).unwrap();
+        let path = dir.root.join("main.rs");
+
+        let red = TestRedirector::default().source(SourceMode::Comments);
+        red.fix_file(&path).unwrap();
+        assert_files(&[(path.clone(), content.replacen("foo1", "piyo1", 1))]);
+
+        let red = TestRedirector::default().source(SourceMode::CommentsAndStrings);
+        red.fix_file(&path).unwrap();
+        assert_files(&[(path, content.replace("foo", "piyo"))]);
+    }
+
+    #[test]
+    fn keep_ampersands_in_jsx_files() {
+        let content = "const url = \"https://example.com/foo?a=1&b=2\";\nconst ok = a <b && <a href={url}>Link</a>;\n";
+        let entries = &[TestDirEntry::File("App.jsx", content)];
+        let want = content.replace("foo", "piyo");
+
+        // .jsx files are not parsed as HTML, so & is not encoded as &amp;
+        for mode in &[SourceMode::Text, SourceMode::CommentsAndStrings] {
+            let dir = TestDir::new(entries).unwrap();
+            let path = dir.root.join("App.jsx");
+            let red = TestRedirector::default().source(*mode);
+            red.fix_file(&path).unwrap();
+            assert_files(&[(path, want.clone())]);
+        }
+    }
+
+    // Extractor which finds only URLs in lines starting with "url = "
+    struct ConfExtractor;
+
+    impl Extractor for ConfExtractor {
+        fn extract(&self, content: &str, _path: Option<&Path>) -> Vec<Span> {
+            let mut spans = vec![];
+            let mut offset = 0;
+            for line in content.split_inclusive('\n') {
+                if let Some(url) = line.strip_prefix("url = ") {
+                    let start = offset + "url = ".len();
+                    spans.push(Span::new(
+                        start,
+                        start + url.trim_end().len(),
+                        UrlContext::Text,
+                    ));
+                }
+                offset += line.len();
+            }
+            spans
+        }
+    }
+
+    #[test]
+    fn fix_files_with_custom_extractor() {
+        let content = "url = https://example.com/foo1\nhttps://example.com/foo2\n";
+        let entries = &[
+            TestDirEntry::File("test.conf", content),
+            TestDirEntry::File("test.txt", content),
+        ];
+        let dir = TestDir::new(entries).unwrap();
+        let conf = dir.root.join("test.conf");
+        let txt = dir.root.join("test.txt");
+
+        let red =
+            TestRedirector::default().extractor(FilePattern::extension("conf"), ConfExtractor);
+        red.fix_all_files(iter::once(dir.root.as_os_str())).unwrap();
+        assert_files(&[
+            (conf, content.replacen("foo1", "piyo1", 1)),
+            (txt, content.replace("foo", "piyo")),
+        ]);
+    }
+
+    #[test]
+    fn encode_redirected_url_in_html() {
+        let link = Link {
+            start: 0,
+            end: 19,
+            outcome: Outcome::Redirected("https://example.com/?a=1&b='2'".to_string()),
+            chain: vec![],
+            context: UrlContext::Html,
+        };
+        assert_eq!(
+            link.replacement().unwrap().text,
+            "https://example.com/?a=1&amp;b=&#39;2&#39;"
+        );
+    }
+
     #[test]
     fn fix_reader_writer() {
         let mut output = vec![];
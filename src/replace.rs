@@ -1,5 +1,10 @@
 use anyhow::Result;
+use std::cmp;
+use std::env;
 use std::io::Write;
+use std::path::{Component, Path};
+
+const DIFF_CONTEXT: usize = 3;
 
 pub struct Replacement {
     pub start: usize,
@@ -12,8 +17,23 @@ impl Replacement {
         let text = text.into();
         Replacement { start, end, text }
     }
+
+    // Returns 1-based line and column numbers of the start of this replacement in the input
+    pub fn position(&self, input: &str) -> (usize, usize) {
+        line_col(input, self.start)
+    }
+}
+
+// Returns 1-based line and column numbers of the byte offset in the input. Column is counted in characters
+pub fn line_col(input: &str, offset: usize) -> (usize, usize) {
+    let before = &input[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let col = before[line_start..].chars().count() + 1;
+    (line, col)
 }
 
+#[allow(clippy::sliced_string_as_bytes)]
 pub fn replace_all<W: Write>(mut out: W, input: &str, replacements: &[Replacement]) -> Result<()> {
     let mut i = 0;
     for replacement in replacements.iter() {
@@ -26,11 +46,153 @@ pub fn replace_all<W: Write>(mut out: W, input: &str, replacements: &[Replacemen
     Ok(out.flush()?)
 }
 
+// Lines in [first, last) are replaced with the text in new
+struct LinesChange {
+    first: usize,
+    last: usize,
+    new: String,
+}
+
+fn write_diff_line<W: Write>(out: &mut W, prefix: &str, line: &str) -> Result<()> {
+    out.write_all(prefix.as_bytes())?;
+    out.write_all(line.as_bytes())?;
+    if !line.ends_with('\n') {
+        out.write_all(b"\n\\ No newline at end of file\n")?;
+    }
+    Ok(())
+}
+
+// Path in headers of unified diff separated with '/'. Absolute paths are made relative to the current directory. Roots
+// of absolute paths outside of it are removed so that the diff can be applied from the root directory
+fn diff_path(path: &Path) -> String {
+    let path = env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok())
+        .unwrap_or(path);
+    let components: Vec<_> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy()),
+            Component::ParentDir => Some("..".into()),
+            _ => None,
+        })
+        .collect();
+    components.join("/")
+}
+
+// Render replacements as unified diff. Paths are prefixed with a/ and b/ so that the output can be applied with
+// `git apply` or `patch -p1`
+pub fn write_unified_diff<W: Write>(
+    mut out: W,
+    path: &Path,
+    input: &str,
+    replacements: &[Replacement],
+) -> Result<()> {
+    if replacements.is_empty() {
+        return Ok(());
+    }
+
+    let lines: Vec<_> = input.split_inclusive('\n').collect();
+    let mut offsets = Vec::with_capacity(lines.len() + 1);
+    let mut offset = 0;
+    for line in lines.iter() {
+        offsets.push(offset);
+        offset += line.len();
+    }
+    offsets.push(offset);
+    let line_at = |idx: usize| match offsets.binary_search(&idx) {
+        Ok(i) => cmp::min(i, lines.len().saturating_sub(1)),
+        Err(i) => i - 1,
+    };
+
+    // Collect changed lines. Replacements in the same line are merged into one change
+    let mut changes: Vec<LinesChange> = vec![];
+    let mut i = 0;
+    while i < replacements.len() {
+        let first = line_at(replacements[i].start);
+        let mut last = line_at(cmp::max(replacements[i].end, replacements[i].start + 1) - 1) + 1;
+        let mut j = i + 1;
+        while j < replacements.len() && line_at(replacements[j].start) < last {
+            let end = cmp::max(replacements[j].end, replacements[j].start + 1) - 1;
+            last = cmp::max(last, line_at(end) + 1);
+            j += 1;
+        }
+
+        let end = offsets[last];
+        let mut new = String::new();
+        let mut idx = offsets[first];
+        for rep in replacements[i..j].iter() {
+            new.push_str(&input[idx..rep.start]);
+            new.push_str(&rep.text);
+            idx = rep.end;
+        }
+        new.push_str(&input[idx..end]);
+
+        changes.push(LinesChange { first, last, new });
+        i = j;
+    }
+
+    let path = diff_path(path);
+    writeln!(out, "--- a/{}", path)?;
+    writeln!(out, "+++ b/{}", path)?;
+
+    // Changes close to each other are rendered in the same hunk since their contexts overlap
+    let mut delta = 0isize;
+    let mut i = 0;
+    while i < changes.len() {
+        let mut j = i + 1;
+        while j < changes.len() && changes[j].first - changes[j - 1].last <= DIFF_CONTEXT * 2 {
+            j += 1;
+        }
+        let hunk = &changes[i..j];
+
+        let old_start = hunk[0].first.saturating_sub(DIFF_CONTEXT);
+        let old_end = cmp::min(hunk[hunk.len() - 1].last + DIFF_CONTEXT, lines.len());
+        let old_len = old_end - old_start;
+        let added: usize = hunk
+            .iter()
+            .map(|c| c.new.split_inclusive('\n').count())
+            .sum();
+        let removed: usize = hunk.iter().map(|c| c.last - c.first).sum();
+        let new_len = old_len + added - removed;
+        let new_start = (old_start as isize + delta) as usize;
+        writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            old_start + 1,
+            old_len,
+            new_start + 1,
+            new_len,
+        )?;
+
+        let mut l = old_start;
+        for change in hunk {
+            for line in lines[l..change.first].iter() {
+                write_diff_line(&mut out, " ", line)?;
+            }
+            for line in lines[change.first..change.last].iter() {
+                write_diff_line(&mut out, "-", line)?;
+            }
+            for line in change.new.split_inclusive('\n') {
+                write_diff_line(&mut out, "+", line)?;
+            }
+            l = change.last;
+        }
+        for line in lines[l..old_end].iter() {
+            write_diff_line(&mut out, " ", line)?;
+        }
+
+        delta += added as isize - removed as isize;
+        i = j;
+    }
+
+    Ok(out.flush()?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_helper::*;
-    use std::array::IntoIter;
     use std::str;
 
     #[test]
@@ -66,7 +228,7 @@ mod tests {
 
     #[test]
     fn no_replacement() {
-        for i in IntoIter::new(["", "foo"]) {
+        for i in ["", "foo"] {
             let mut buf = Vec::new();
             replace_all(&mut buf, i, &[]).unwrap();
             let o = str::from_utf8(&buf).unwrap();
@@ -74,6 +236,111 @@ mod tests {
         }
     }
 
+    #[test]
+    fn replacement_position() {
+        let input = "foo\nhi! hello\nあいう https://example.com";
+        let tests = &[(0, (1, 1)), (8, (2, 5)), (14, (3, 1)), (24, (3, 5))];
+        for (start, want) in tests {
+            let rep = Replacement::new(*start, *start, "");
+            assert_eq!(rep.position(input), *want, "start={}", start);
+        }
+    }
+
+    #[test]
+    fn diff_one_line() {
+        let mut buf = Vec::new();
+        let input = "a\nb\nc\nd\nhi! hello world!\ne\nf\ng\nh\n";
+        let rep = &[Replacement::new(12, 12 + "hello".len(), "goodbye")];
+        write_unified_diff(&mut buf, Path::new("./foo/bar.txt"), input, rep).unwrap();
+        let have = str::from_utf8(&buf).unwrap();
+        let want = "--- a/foo/bar.txt
++++ b/foo/bar.txt
+@@ -2,7 +2,7 @@
+ b
+ c
+ d
+-hi! hello world!
++hi! goodbye world!
+ e
+ f
+ g
+";
+        assert_eq!(have, want);
+    }
+
+    #[test]
+    fn diff_multiple_hunks() {
+        let mut buf = Vec::new();
+        let input = "foo\n1\n2\n3\n4\n5\n6\nfoo foo\n7\n8\n9\n10\n11\n12\n13\nfoo";
+        let reps: Vec<_> = input
+            .match_indices("foo")
+            .map(|(i, s)| Replacement::new(i, i + s.len(), "piyo"))
+            .collect();
+        write_unified_diff(&mut buf, Path::new("test.txt"), input, &reps).unwrap();
+        let have = str::from_utf8(&buf).unwrap();
+        let want = "--- a/test.txt
++++ b/test.txt
+@@ -1,11 +1,11 @@
+-foo
++piyo
+ 1
+ 2
+ 3
+ 4
+ 5
+ 6
+-foo foo
++piyo piyo
+ 7
+ 8
+ 9
+@@ -13,4 +13,4 @@
+ 11
+ 12
+ 13
+-foo
+\\ No newline at end of file
++piyo
+\\ No newline at end of file
+";
+        assert_eq!(have, want);
+    }
+
+    #[test]
+    fn path_in_diff() {
+        let cwd = env::current_dir().unwrap();
+        let tests = &[
+            (Path::new("foo/bar.txt").to_path_buf(), "foo/bar.txt"),
+            (Path::new("./foo/./bar.txt").to_path_buf(), "foo/bar.txt"),
+            (Path::new("../foo/bar.txt").to_path_buf(), "../foo/bar.txt"),
+            (cwd.join("foo").join("bar.txt"), "foo/bar.txt"),
+            (
+                Path::new("/no/such/dir/bar.txt").to_path_buf(),
+                "no/such/dir/bar.txt",
+            ),
+        ];
+        for (path, want) in tests {
+            assert_eq!(diff_path(path), *want, "{:?}", path);
+        }
+
+        let mut buf = Vec::new();
+        let rep = &[Replacement::new(0, 3, "bar")];
+        write_unified_diff(&mut buf, Path::new("/abs/test.txt"), "foo\n", rep).unwrap();
+        let have = String::from_utf8(buf).unwrap();
+        assert!(
+            have.starts_with("--- a/abs/test.txt\n+++ b/abs/test.txt\n"),
+            "{}",
+            have
+        );
+    }
+
+    #[test]
+    fn diff_no_replacement() {
+        let mut buf = Vec::new();
+        write_unified_diff(&mut buf, Path::new("test.txt"), "foo", &[]).unwrap();
+        assert!(buf.is_empty());
+    }
+
     #[test]
     fn write_error() {
         assert!(replace_all(WriteErrorWriter, "foo", &[]).is_err());
@@ -83,4 +350,12 @@ mod tests {
     fn flush_error() {
         assert!(replace_all(FlushErrorWriter, "foo", &[]).is_err());
     }
+
+    #[test]
+    fn diff_write_error() {
+        let rep = &[Replacement::new(0, 3, "bar")];
+        let path = Path::new("test.txt");
+        assert!(write_unified_diff(WriteErrorWriter, path, "foo", rep).is_err());
+        assert!(write_unified_diff(FlushErrorWriter, path, "foo", rep).is_err());
+    }
 }
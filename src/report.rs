@@ -0,0 +1,516 @@
+use crate::redirect::{FileLinks, FileReplacements, Summary};
+use crate::replace::line_col;
+use crate::resolve::{Hop, NetworkErrorKind, Outcome};
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::Path;
+
+const SARIF_RULE_ID: &str = "outdated-link";
+
+#[derive(Serialize)]
+struct LinkReport<'a> {
+    file: String,
+    start: usize,
+    end: usize,
+    line: usize,
+    column: usize,
+    url: Cow<'a, str>,
+    filtered: bool,
+    outcome: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_kind: Option<NetworkErrorKind>,
+    final_url: Option<Cow<'a, str>>,
+    chain: &'a [Hop],
+}
+
+fn link_reports(files: &[FileLinks]) -> impl Iterator<Item = LinkReport<'_>> {
+    files.iter().flat_map(|file| {
+        file.links.iter().map(move |link| {
+            let url = link.context.decode(&file.content[link.start..link.end]);
+            let (line, column) = line_col(&file.content, link.start);
+            let mut status = None;
+            let mut error = None;
+            let mut error_kind = None;
+            let final_url = match &link.outcome {
+                Outcome::NotRedirected => Some(url.clone()),
+                Outcome::Redirected(to) => Some(Cow::Borrowed(to.as_str())),
+                Outcome::HttpError(s) => {
+                    status = Some(*s);
+                    None
+                }
+                Outcome::NetworkError(kind, msg) => {
+                    error = Some(msg.as_str());
+                    error_kind = Some(*kind);
+                    None
+                }
+                Outcome::Skipped => None,
+            };
+            let filtered = link.outcome == Outcome::Skipped;
+            let outcome = (!filtered).then(|| link.outcome.kind());
+            LinkReport {
+                file: file.path.display().to_string(),
+                start: link.start,
+                end: link.end,
+                line,
+                column,
+                url,
+                filtered,
+                outcome,
+                status,
+                error,
+                error_kind,
+                final_url,
+                chain: &link.chain,
+            }
+        })
+    })
+}
+
+// Output all links as one JSON array
+pub fn write_json<W: Write>(mut out: W, files: &[FileLinks]) -> Result<()> {
+    let reports: Vec<_> = link_reports(files).collect();
+    serde_json::to_writer_pretty(&mut out, &reports)?;
+    out.write_all(b"\n")?;
+    Ok(out.flush()?)
+}
+
+// Output each link as one JSON object per line
+pub fn write_json_lines<W: Write>(mut out: W, files: &[FileLinks]) -> Result<()> {
+    for report in link_reports(files) {
+        serde_json::to_writer(&mut out, &report)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(out.flush()?)
+}
+
+// Output broken links grouped by files with their line and column numbers
+pub fn write_broken_links<W: Write>(mut out: W, summary: &Summary) -> Result<()> {
+    let groups = summary.broken_by_file();
+    for (path, links) in groups.iter() {
+        match path {
+            Some(path) => writeln!(out, "{}", path.display())?,
+            None => writeln!(out, "<stdin>")?,
+        }
+        for link in links {
+            writeln!(
+                out,
+                "  {}:{}: {} ({})",
+                link.line, link.column, link.url, link.outcome
+            )?;
+        }
+    }
+    if !groups.is_empty() {
+        writeln!(
+            out,
+            "Found {} broken link(s) in {} file(s)",
+            summary.broken.len(),
+            groups.len()
+        )?;
+    }
+    Ok(out.flush()?)
+}
+
+// Relative paths are converted into relative references and absolute paths are converted into file:// URIs. Characters
+// which are not allowed in path segments such as spaces, '#' and '%' are percent-encoded
+// https://datatracker.ietf.org/doc/html/rfc3986#section-3.3
+fn sarif_uri(path: &Path) -> String {
+    let path = path.strip_prefix(".").unwrap_or(path);
+    let absolute = path.is_absolute();
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::new();
+    if absolute {
+        uri.push_str("file://");
+        // Windows path like C:/foo
+        if !path.starts_with('/') {
+            uri.push('/');
+        }
+    }
+    for b in path.bytes() {
+        // Unreserved characters, sub-delims, '@' and '/'. ':' in the first segment of a relative reference would be
+        // parsed as a scheme
+        let allowed = b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=@/".contains(&b);
+        if allowed || (b == b':' && absolute) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+// Output redirected links as results of SARIF 2.1.0. Each result has a fix to replace the link with the redirected one
+// https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+pub fn write_sarif<W: Write>(mut out: W, files: &[FileReplacements]) -> Result<()> {
+    let mut results = vec![];
+    for file in files {
+        let uri = sarif_uri(&file.path);
+        for rep in file.replacements.iter() {
+            let url = &file.content[rep.start..rep.end];
+            let (start_line, start_column) = line_col(&file.content, rep.start);
+            let (end_line, end_column) = line_col(&file.content, rep.end);
+            let region = json!({
+                "startLine": start_line,
+                "startColumn": start_column,
+                "endLine": end_line,
+                "endColumn": end_column,
+                "byteOffset": rep.start,
+                "byteLength": rep.end - rep.start,
+            });
+            results.push(json!({
+                "ruleId": SARIF_RULE_ID,
+                "ruleIndex": 0,
+                "level": "warning",
+                "message": {
+                    "text": format!("Link {} is redirected to {}", url, rep.text),
+                },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": &uri },
+                        "region": &region,
+                    },
+                }],
+                "fixes": [{
+                    "description": { "text": "Replace the link with the redirected URL" },
+                    "artifactChanges": [{
+                        "artifactLocation": { "uri": &uri },
+                        "replacements": [{
+                            "deletedRegion": region,
+                            "insertedContent": { "text": &rep.text },
+                        }],
+                    }],
+                }],
+            }));
+        }
+    }
+
+    let sarif = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "fixred",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/rhysd/fixred",
+                    "rules": [{
+                        "id": SARIF_RULE_ID,
+                        "shortDescription": { "text": "Outdated link which is redirected to another URL" },
+                        "helpUri": "https://github.com/rhysd/fixred#usage",
+                    }],
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": Value::Array(results),
+        }],
+    });
+    serde_json::to_writer_pretty(&mut out, &sarif)?;
+    out.write_all(b"\n")?;
+    Ok(out.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redirect::{BrokenLink, Link};
+    use crate::replace::Replacement;
+    use crate::test_helper::*;
+    use crate::url::UrlContext;
+    use std::path::PathBuf;
+    use std::str;
+
+    fn test_files() -> Vec<FileLinks> {
+        let content = "foo https://a.example.com\n  https://b.example.com https://c.example.com\nhttps://d.example.com https://e.example.com";
+        let link = |url: &str, outcome, chain| {
+            let start = content.find(url).unwrap();
+            Link {
+                start,
+                end: start + url.len(),
+                outcome,
+                chain,
+                context: UrlContext::Text,
+            }
+        };
+        let hop = |url: &str, status, location: Option<&str>| Hop {
+            url: url.to_string(),
+            status,
+            location: location.map(ToString::to_string),
+        };
+        let links = vec![
+            link(
+                "https://a.example.com",
+                Outcome::Redirected("https://a2.example.com".to_string()),
+                vec![
+                    hop("https://a.example.com", 301, Some("/a2")),
+                    hop("https://a2.example.com", 200, None),
+                ],
+            ),
+            link(
+                "https://b.example.com",
+                Outcome::NotRedirected,
+                vec![hop("https://b.example.com", 200, None)],
+            ),
+            link(
+                "https://c.example.com",
+                Outcome::NetworkError(NetworkErrorKind::Timeout, "oops".to_string()),
+                vec![],
+            ),
+            link("https://d.example.com", Outcome::Skipped, vec![]),
+            link(
+                "https://e.example.com",
+                Outcome::HttpError(404),
+                vec![hop("https://e.example.com", 404, None)],
+            ),
+        ];
+        vec![FileLinks {
+            path: PathBuf::from("test.txt"),
+            content: content.to_string(),
+            links,
+        }]
+    }
+
+    fn want_reports() -> Vec<Value> {
+        vec![
+            json!({
+                "file": "test.txt",
+                "start": 4,
+                "end": 25,
+                "line": 1,
+                "column": 5,
+                "url": "https://a.example.com",
+                "filtered": false,
+                "outcome": "redirected",
+                "final_url": "https://a2.example.com",
+                "chain": [
+                    { "url": "https://a.example.com", "status": 301, "location": "/a2" },
+                    { "url": "https://a2.example.com", "status": 200, "location": null },
+                ],
+            }),
+            json!({
+                "file": "test.txt",
+                "start": 28,
+                "end": 49,
+                "line": 2,
+                "column": 3,
+                "url": "https://b.example.com",
+                "filtered": false,
+                "outcome": "not_redirected",
+                "final_url": "https://b.example.com",
+                "chain": [{ "url": "https://b.example.com", "status": 200, "location": null }],
+            }),
+            json!({
+                "file": "test.txt",
+                "start": 50,
+                "end": 71,
+                "line": 2,
+                "column": 25,
+                "url": "https://c.example.com",
+                "filtered": false,
+                "outcome": "network_error",
+                "error": "oops",
+                "error_kind": "timeout",
+                "final_url": null,
+                "chain": [],
+            }),
+            json!({
+                "file": "test.txt",
+                "start": 72,
+                "end": 93,
+                "line": 3,
+                "column": 1,
+                "url": "https://d.example.com",
+                "filtered": true,
+                "outcome": null,
+                "final_url": null,
+                "chain": [],
+            }),
+            json!({
+                "file": "test.txt",
+                "start": 94,
+                "end": 115,
+                "line": 3,
+                "column": 23,
+                "url": "https://e.example.com",
+                "filtered": false,
+                "outcome": "http_error",
+                "status": 404,
+                "final_url": null,
+                "chain": [{ "url": "https://e.example.com", "status": 404, "location": null }],
+            }),
+        ]
+    }
+
+    #[test]
+    fn json_report() {
+        let mut buf = Vec::new();
+        write_json(&mut buf, &test_files()).unwrap();
+        let have: Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(have, Value::Array(want_reports()));
+    }
+
+    #[test]
+    fn json_lines_report() {
+        let mut buf = Vec::new();
+        write_json_lines(&mut buf, &test_files()).unwrap();
+        let have: Vec<Value> = str::from_utf8(&buf)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(have, want_reports());
+    }
+
+    #[test]
+    fn empty_report() {
+        let mut buf = Vec::new();
+        write_json(&mut buf, &[]).unwrap();
+        assert_eq!(str::from_utf8(&buf).unwrap(), "[]\n");
+
+        let mut buf = Vec::new();
+        write_json_lines(&mut buf, &[]).unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn sarif_report() {
+        let content = "foo\n  あ https://foo.example.com bar\n";
+        let files = &[FileReplacements {
+            path: PathBuf::from("./docs/test.md"),
+            content: content.to_string(),
+            replacements: vec![Replacement::new(
+                10,
+                10 + "https://foo.example.com".len(),
+                "https://piyo.example.com",
+            )],
+        }];
+        let mut buf = Vec::new();
+        write_sarif(&mut buf, files).unwrap();
+        let have: Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(have["version"], "2.1.0");
+        let run = &have["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "fixred");
+        assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "outdated-link");
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        let result = &results[0];
+        assert_eq!(result["ruleId"], "outdated-link");
+        assert_eq!(
+            result["message"]["text"],
+            "Link https://foo.example.com is redirected to https://piyo.example.com",
+        );
+
+        let region = json!({
+            "startLine": 2,
+            "startColumn": 5,
+            "endLine": 2,
+            "endColumn": 28,
+            "byteOffset": 10,
+            "byteLength": 23,
+        });
+        let loc = &result["locations"][0]["physicalLocation"];
+        assert_eq!(loc["artifactLocation"]["uri"], "docs/test.md");
+        assert_eq!(loc["region"], region);
+
+        let change = &result["fixes"][0]["artifactChanges"][0];
+        assert_eq!(change["artifactLocation"]["uri"], "docs/test.md");
+        let rep = &change["replacements"][0];
+        assert_eq!(rep["deletedRegion"], region);
+        assert_eq!(rep["insertedContent"]["text"], "https://piyo.example.com");
+    }
+
+    #[test]
+    fn sarif_uri_of_path() {
+        let tests = &[
+            ("docs/test.md", "docs/test.md"),
+            ("./docs/test.md", "docs/test.md"),
+            ("my docs/#1 100%.md", "my%20docs/%231%20100%25.md"),
+            ("a:b/c@d.md", "a%3Ab/c@d.md"),
+            ("docs/テスト.md", "docs/%E3%83%86%E3%82%B9%E3%83%88.md"),
+            ("/tmp/my docs/a:b.md", "file:///tmp/my%20docs/a:b.md"),
+        ];
+        for (path, want) in tests {
+            assert_eq!(sarif_uri(Path::new(path)), *want, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn empty_sarif_report() {
+        let mut buf = Vec::new();
+        write_sarif(&mut buf, &[]).unwrap();
+        let have: Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(have["runs"][0]["results"], json!([]));
+    }
+
+    #[test]
+    fn broken_links_report() {
+        let broken = |path: Option<&str>, start, line, column, url: &str, outcome| BrokenLink {
+            path: path.map(PathBuf::from),
+            start,
+            line,
+            column,
+            url: url.to_string(),
+            outcome,
+        };
+        let summary = Summary {
+            broken: vec![
+                broken(
+                    Some("a.md"),
+                    30,
+                    3,
+                    1,
+                    "https://b.example.com",
+                    Outcome::NetworkError(NetworkErrorKind::Dns, "oops".to_string()),
+                ),
+                broken(
+                    Some("b.md"),
+                    0,
+                    1,
+                    1,
+                    "https://c.example.com",
+                    Outcome::HttpError(500),
+                ),
+                broken(
+                    Some("a.md"),
+                    4,
+                    1,
+                    5,
+                    "https://a.example.com",
+                    Outcome::HttpError(404),
+                ),
+            ],
+            ..Summary::default()
+        };
+
+        let mut buf = Vec::new();
+        write_broken_links(&mut buf, &summary).unwrap();
+        let have = str::from_utf8(&buf).unwrap();
+        let want = "a.md
+  1:5: https://a.example.com (HTTP error with status 404)
+  3:1: https://b.example.com (DNS error: oops)
+b.md
+  1:1: https://c.example.com (HTTP error with status 500)
+Found 3 broken link(s) in 2 file(s)
+";
+        assert_eq!(have, want);
+
+        let mut buf = Vec::new();
+        write_broken_links(&mut buf, &Summary::default()).unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn write_error() {
+        assert!(write_json(WriteErrorWriter, &test_files()).is_err());
+        assert!(write_json_lines(WriteErrorWriter, &test_files()).is_err());
+        assert!(write_json(FlushErrorWriter, &test_files()).is_err());
+        assert!(write_sarif(WriteErrorWriter, &[]).is_err());
+    }
+}
@@ -1,55 +1,705 @@
-use anyhow::Result;
+use crate::cache::DiskCache;
+use crate::header::{RequestHeaders, DEFAULT_USER_AGENT};
+use crate::limit::HostLimiter;
+use crate::url::{join_url, redact_userinfo};
 use chashmap::CHashMap;
-use curl::easy::Easy;
+use curl::easy::{Easy, List, SslVersion};
 use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-pub trait Resolver: Default + Sync {
+// One HTTP request while following redirects
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Hop {
+    pub url: String,
+    pub status: u32,
+    // Raw value of Location header. It may be a relative URL
+    pub location: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkErrorKind {
+    Dns,
+    Connect,
+    Timeout,
+    Tls,
+    TooManyRedirects,
+    InvalidUrl,
+    Other,
+}
+
+impl NetworkErrorKind {
+    fn from_curl_error(err: &curl::Error) -> Self {
+        if err.is_couldnt_resolve_host() || err.is_couldnt_resolve_proxy() {
+            Self::Dns
+        } else if err.is_couldnt_connect() {
+            Self::Connect
+        } else if err.is_operation_timedout() {
+            Self::Timeout
+        } else if err.is_ssl_connect_error()
+            || err.is_peer_failed_verification()
+            || err.is_ssl_certproblem()
+            || err.is_ssl_cacert()
+            || err.is_ssl_cacert_badfile()
+            || err.is_ssl_cipher()
+        {
+            Self::Tls
+        } else if err.is_too_many_redirects() {
+            Self::TooManyRedirects
+        } else if err.is_url_malformed() || err.is_unsupported_protocol() {
+            Self::InvalidUrl
+        } else {
+            Self::Other
+        }
+    }
+}
+
+impl fmt::Display for NetworkErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Dns => "DNS error",
+            Self::Connect => "connection error",
+            Self::Timeout => "timeout",
+            Self::Tls => "TLS error",
+            Self::TooManyRedirects => "too many redirects",
+            Self::InvalidUrl => "invalid URL",
+            Self::Other => "network error",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "outcome", content = "value")]
+pub enum Outcome {
+    Redirected(String),
+    NotRedirected,
+    // The URL answered with 4xx or 5xx status
+    HttpError(u32),
+    // The request failed. The second element is the error message
+    NetworkError(NetworkErrorKind, String),
+    // The URL was not resolved. For example, it was filtered with --extract or --ignore
+    Skipped,
+}
+
+impl Outcome {
+    pub fn is_broken(&self) -> bool {
+        matches!(self, Self::HttpError(_) | Self::NetworkError(..))
+    }
+
+    // Name of the outcome in reports. It is the same as the "outcome" tag of serialized resolutions
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Redirected(_) => "redirected",
+            Self::NotRedirected => "not_redirected",
+            Self::HttpError(_) => "http_error",
+            Self::NetworkError(..) => "network_error",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Redirected(url) => write!(f, "redirected to {}", url),
+            Self::NotRedirected => f.write_str("not redirected"),
+            Self::HttpError(status) => write!(f, "HTTP error with status {}", status),
+            Self::NetworkError(kind, msg) => write!(f, "{}: {}", kind, msg),
+            Self::Skipped => f.write_str("skipped"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Resolution {
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    // All requests sent while resolving the URL in order. The last hop tells why following redirects stopped
+    pub chain: Vec<Hop>,
+}
+
+impl Resolution {
+    pub fn new(outcome: Outcome) -> Self {
+        Self {
+            outcome,
+            chain: vec![],
+        }
+    }
+
+    pub fn redirect(&self) -> Option<&str> {
+        if let Outcome::Redirected(url) = &self.outcome {
+            Some(url)
+        } else {
+            None
+        }
+    }
+
+    pub fn chain_display(&self) -> ChainDisplay<'_> {
+        ChainDisplay(&self.chain)
+    }
+}
+
+pub struct ChainDisplay<'a>(&'a [Hop]);
+
+impl<'a> fmt::Display for ChainDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, hop) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" -> ")?;
+            }
+            write!(f, "{} ({})", hop.url, hop.status)?;
+        }
+        Ok(())
+    }
+}
+
+// Resolvers can be composed with combinators in combinator module
+pub trait Resolver: Sync {
     fn shallow(&mut self, b: bool);
-    fn resolve(&self, url: &str) -> Option<String>;
+    // Resolving a URL never fails since it is normal case that broken URL is passed to this function. How the URL was
+    // resolved is returned as outcome
+    fn resolve(&self, url: &str) -> Resolution;
+}
+
+// Status codes of redirects which mean the resource was moved permanently
+pub const PERMANENT_REDIRECTS: &[u32] = &[301, 308];
+const MAX_REDIRECTS: usize = 30;
+// Some servers reject HEAD requests with these statuses though GET requests work fine
+const HEAD_REJECTED: &[u32] = &[403, 404, 405, 501];
+// Statuses of temporary failures which are worth retrying
+const RETRYABLE_STATUS: &[u32] = &[429, 502, 503, 504];
+// Delays of retries are capped to this to avoid stalling for a long time. Longer Retry-After header is shortened to it
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_RETRIES: u32 = 2;
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+fn is_retryable_error(err: &curl::Error) -> bool {
+    err.is_operation_timedout()
+        || err.is_couldnt_connect()
+        || err.is_send_error()
+        || err.is_recv_error()
+        || err.is_got_nothing()
+        || err.is_partial_file()
+}
+
+// Retry-After header value is delay seconds or HTTP date
+// https://datatracker.ietf.org/doc/html/rfc7231#section-7.1.3
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+// Delay before retrying a request. Retry-After header sent by the server is honored unless it is shorter than the
+// backoff. It is capped to MAX_RETRY_AFTER
+fn retry_delay(retry_after: Option<Duration>, backoff: Duration) -> Duration {
+    match retry_after {
+        Some(d) => d.max(backoff).min(MAX_RETRY_AFTER),
+        None => backoff,
+    }
+}
+
+// Exponential backoff with jitter. The delay of n-th retry (starting from 0) is randomly chosen from
+// [base * 2^n / 2, base * 2^n). The delay is capped to MAX_RETRY_AFTER
+fn backoff_delay(base: Duration, retry: u32) -> Duration {
+    let delay = base
+        .checked_mul(2u32.saturating_pow(retry.min(16)))
+        .unwrap_or(MAX_RETRY_AFTER)
+        .min(MAX_RETRY_AFTER);
+    let half = delay / 2;
+    let nanos = half.as_nanos() as u64;
+    if nanos == 0 {
+        return delay;
+    }
+    // Jitter does not need a good random number. RandomState is seeded with random keys on each construction so the
+    // hash of no input is random enough without depending on a crate for random numbers
+    let random = RandomState::new().build_hasher().finish();
+    half + Duration::from_nanos(random % nanos)
+}
+
+// Response of one request
+struct Response {
+    hop: Hop,
+    // Absolute URL of the redirect target
+    next: Option<String>,
+    retry_after: Option<Duration>,
+}
+
+// HTTP method to send for resolving URLs
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Method {
+    Head,
+    // GET request is aborted after receiving headers so that response body is not downloaded
+    Get,
+    // Send HEAD request first and retry with GET request when the server rejects HEAD request
+    #[default]
+    HeadThenGet,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "head" => Ok(Self::Head),
+            "get" => Ok(Self::Get),
+            "head-then-get" => Ok(Self::HeadThenGet),
+            _ => Err(format!(
+                "Unknown method policy {:?}. It must be one of \"head\", \"get\", \"head-then-get\"",
+                s
+            )),
+        }
+    }
+}
+
+// Minimum TLS version to connect to servers
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TlsVersion {
+    V1_0,
+    V1_1,
+    V1_2,
+    V1_3,
+}
+
+impl TlsVersion {
+    fn to_curl(self) -> SslVersion {
+        match self {
+            Self::V1_0 => SslVersion::Tlsv10,
+            Self::V1_1 => SslVersion::Tlsv11,
+            Self::V1_2 => SslVersion::Tlsv12,
+            Self::V1_3 => SslVersion::Tlsv13,
+        }
+    }
+}
+
+impl FromStr for TlsVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1.0" => Ok(Self::V1_0),
+            "1.1" => Ok(Self::V1_1),
+            "1.2" => Ok(Self::V1_2),
+            "1.3" => Ok(Self::V1_3),
+            _ => Err(format!(
+                "Unknown TLS version {:?}. It must be one of \"1.0\", \"1.1\", \"1.2\", \"1.3\"",
+                s
+            )),
+        }
+    }
 }
 
-#[derive(Default)]
 pub struct CurlResolver {
     shallow: bool,
-    cache: CHashMap<String, Option<String>>,
+    allowed_status: Vec<u32>,
+    method: Method,
+    connect_timeout: Duration,
+    timeout: Duration,
+    retries: u32,
+    retry_backoff: Duration,
+    limiter: HostLimiter,
+    user_agent: String,
+    headers: RequestHeaders,
+    proxy: Option<String>,
+    no_proxy: Option<String>,
+    ca_cert: Option<PathBuf>,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+    min_tls_version: Option<TlsVersion>,
+    insecure: bool,
+    cache: CHashMap<String, Resolution>,
+    disk_cache: Option<Arc<DiskCache>>,
+}
+
+impl Default for CurlResolver {
+    fn default() -> Self {
+        Self {
+            shallow: false,
+            allowed_status: PERMANENT_REDIRECTS.to_vec(),
+            method: Method::default(),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            limiter: HostLimiter::default(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            headers: RequestHeaders::default(),
+            proxy: None,
+            no_proxy: None,
+            ca_cert: None,
+            client_cert: None,
+            client_key: None,
+            min_tls_version: None,
+            insecure: false,
+            cache: CHashMap::new(),
+            disk_cache: None,
+        }
+    }
 }
 
 impl CurlResolver {
-    fn try_resolve(&self, url: &str) -> Result<Option<String>> {
-        debug!("Resolving {}", url);
+    // Set status codes of redirects to follow. By default only permanent redirects (301 and 308) are followed so that
+    // temporary redirects such as login pages are not applied
+    pub fn allow_status(mut self, codes: Vec<u32>) -> Self {
+        debug!("Status codes of redirects to follow: {:?}", codes);
+        self.allowed_status = codes;
+        self
+    }
+
+    // Set HTTP method to send. By default HEAD request is sent and GET request is sent only when the server rejects
+    // the HEAD request
+    pub fn method(mut self, method: Method) -> Self {
+        debug!("Method policy: {:?}", method);
+        self.method = method;
+        self
+    }
+
+    // Set timeout for connecting to a server. Zero means no timeout
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        debug!("Connect timeout: {:?}", timeout);
+        self.connect_timeout = timeout;
+        self
+    }
+
+    // Set timeout for each request including connecting to a server. Zero means no timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        debug!("Request timeout: {:?}", timeout);
+        self.timeout = timeout;
+        self
+    }
+
+    // Set how many times a request is retried on temporary failures such as timeouts, connection resets and status
+    // 429, 502, 503, 504
+    pub fn retries(mut self, count: u32) -> Self {
+        debug!("Retry count: {}", count);
+        self.retries = count;
+        self
+    }
+
+    // Set base delay of exponential backoff on retrying requests. Retry-After header is honored when a server sends it
+    // The base delay is capped to MAX_RETRY_AFTER
+    pub fn retry_backoff(mut self, base: Duration) -> Self {
+        let base = base.min(MAX_RETRY_AFTER);
+        debug!("Retry backoff: {:?}", base);
+        self.retry_backoff = base;
+        self
+    }
+
+    // Set scheduler to limit concurrency and requests per second for each host. By default requests are not limited
+    pub fn limiter(mut self, limiter: HostLimiter) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    // Set User-Agent header. By default "fixred/{version}" is sent since some hosts reject requests without it
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        debug!("User agent: {:?}", self.user_agent);
+        self
+    }
+
+    // Set headers sent with requests. Values are not logged since they may contain secrets such as tokens
+    pub fn headers(mut self, headers: RequestHeaders) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    // Set proxy URL such as "http://proxy.example.com:8080". By default the proxy is taken from $http_proxy,
+    // $https_proxy and $all_proxy environment variables
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        let url = url.into();
+        // Do not log credentials in the proxy URL
+        debug!("Proxy: {:?}", redact_userinfo(&url));
+        self.proxy = Some(url);
+        self
+    }
+
+    // Set comma-separated hosts which are connected without the proxy. "*" disables the proxy for all hosts. By
+    // default it is taken from $no_proxy environment variable
+    pub fn no_proxy(mut self, hosts: impl Into<String>) -> Self {
+        self.no_proxy = Some(hosts.into());
+        debug!("No proxy: {:?}", self.no_proxy);
+        self
+    }
+
+    // Set CA bundle file in PEM to verify server certificates instead of the system's one. This is necessary behind a
+    // TLS-intercepting proxy
+    pub fn ca_cert(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_cert = Some(path.into());
+        debug!("CA certificate: {:?}", self.ca_cert);
+        self
+    }
+
+    // Set client certificate in PEM for mutual TLS. When the key is not given, the certificate file must contain the
+    // private key
+    pub fn client_cert(mut self, cert: impl Into<PathBuf>, key: Option<PathBuf>) -> Self {
+        self.client_cert = Some(cert.into());
+        self.client_key = key;
+        debug!(
+            "Client certificate: {:?}, key: {:?}",
+            self.client_cert, self.client_key
+        );
+        self
+    }
+
+    pub fn min_tls_version(mut self, version: TlsVersion) -> Self {
+        debug!("Minimum TLS version: {:?}", version);
+        self.min_tls_version = Some(version);
+        self
+    }
+
+    // Disable verifying server certificates and host names. This is insecure and only for debugging
+    pub fn insecure(mut self, enabled: bool) -> Self {
+        debug!("Insecure: {}", enabled);
+        self.insecure = enabled;
+        self
+    }
 
-        if let Some(u) = self.cache.get(url) {
-            debug!("Cache hit: {} -> {:?}", url, *u);
-            return Ok(u.clone());
+    // Set persistent cache shared across runs. Resolutions in the cache are used instead of sending requests until
+    // they are expired
+    pub fn disk_cache(mut self, cache: Arc<DiskCache>) -> Self {
+        debug!("Cache file: {:?}", cache.path());
+        self.disk_cache = Some(cache);
+        self
+    }
+
+    // Fingerprint of the options which change resolutions. Entries in the cache file are looked up with it so that
+    // resolutions with other options are not reused. The hash may change with Rust versions, which only causes cache
+    // misses. Timeouts, retries and limits are not included since they don't change successful resolutions
+    fn policy(&self) -> String {
+        let mut h = DefaultHasher::new();
+        self.shallow.hash(&mut h);
+        self.allowed_status.hash(&mut h);
+        self.method.hash(&mut h);
+        self.user_agent.hash(&mut h);
+        self.headers.hash(&mut h);
+        self.proxy.hash(&mut h);
+        self.no_proxy.hash(&mut h);
+        self.ca_cert.hash(&mut h);
+        self.client_cert.hash(&mut h);
+        self.client_key.hash(&mut h);
+        self.min_tls_version.hash(&mut h);
+        self.insecure.hash(&mut h);
+        format!("{:016x}", h.finish())
+    }
+
+    fn send_with_retry(&self, url: &str) -> Result<Response, curl::Error> {
+        let mut retry = 0;
+        loop {
+            let ret = self.send_request(url);
+            if retry >= self.retries {
+                return ret;
+            }
+            let delay = match &ret {
+                Ok(res) if RETRYABLE_STATUS.contains(&res.hop.status) => {
+                    if let Some(d) = res.retry_after.filter(|d| *d > MAX_RETRY_AFTER) {
+                        warn!(
+                            "Retry-After of {} is {:?} but retrying after {:?}",
+                            url, d, MAX_RETRY_AFTER,
+                        );
+                    }
+                    retry_delay(res.retry_after, backoff_delay(self.retry_backoff, retry))
+                }
+                Err(err) if is_retryable_error(err) => backoff_delay(self.retry_backoff, retry),
+                _ => return ret,
+            };
+            retry += 1;
+            match &ret {
+                Ok(res) => debug!(
+                    "Retrying request to {} ({}/{}) after {:?} due to status {}",
+                    url, retry, self.retries, delay, res.hop.status,
+                ),
+                Err(err) => debug!(
+                    "Retrying request to {} ({}/{}) after {:?} due to error: {}",
+                    url, retry, self.retries, delay, err,
+                ),
+            }
+            thread::sleep(delay);
         }
+    }
 
-        // https://datatracker.ietf.org/doc/html/rfc3986#section-3
-        let fragment = url.find('#').map(|i| &url[i + 1..]);
+    fn send_request(&self, url: &str) -> Result<Response, curl::Error> {
+        match self.method {
+            Method::Head => self.send(url, false),
+            Method::Get => self.send(url, true),
+            Method::HeadThenGet => {
+                let res = self.send(url, false)?;
+                if !HEAD_REJECTED.contains(&res.hop.status) {
+                    return Ok(res);
+                }
+                debug!(
+                    "HEAD request to {} was rejected with status {}. Retrying with GET request",
+                    url, res.hop.status,
+                );
+                self.send(url, true)
+            }
+        }
+    }
 
-        debug!("Sending HEAD request to {}", url);
+    fn send(&self, url: &str, get: bool) -> Result<Response, curl::Error> {
+        debug!(
+            "Sending {} request to {}",
+            if get { "GET" } else { "HEAD" },
+            url
+        );
+        let _permit = self.limiter.acquire(url);
         let mut curl = Easy::new();
-        curl.nobody(true)?;
-        curl.url(url)?;
-        let resolved = if self.shallow {
-            curl.perform()?;
-            curl.redirect_url()? // Get the first redirect URL
+        if get {
+            curl.get(true)?;
         } else {
-            curl.follow_location(true)?;
-            curl.perform()?;
-            curl.effective_url()?
+            curl.nobody(true)?;
+        }
+        curl.url(url)?;
+        curl.connect_timeout(self.connect_timeout)?;
+        curl.timeout(self.timeout)?;
+        curl.useragent(&self.user_agent)?;
+        if let Some(proxy) = &self.proxy {
+            curl.proxy(proxy)?;
+        }
+        if let Some(hosts) = &self.no_proxy {
+            curl.noproxy(hosts)?;
+        }
+        if let Some(path) = &self.ca_cert {
+            curl.cainfo(path)?;
+        }
+        if let Some(path) = &self.client_cert {
+            curl.ssl_cert(path)?;
+        }
+        if let Some(path) = &self.client_key {
+            curl.ssl_key(path)?;
+        }
+        if let Some(version) = self.min_tls_version {
+            curl.ssl_version(version.to_curl())?;
+        }
+        if self.insecure {
+            curl.ssl_verify_peer(false)?;
+            curl.ssl_verify_host(false)?;
+        }
+        let headers = self.headers.for_url(url);
+        if !headers.is_empty() {
+            let mut list = List::new();
+            for (name, value) in headers {
+                debug!("Sending header {:?} to {}", name, url);
+                list.append(&format!("{}: {}", name, value))?;
+            }
+            curl.http_headers(list)?;
+        }
+        let mut location = None;
+        let mut retry_after = None;
+        {
+            let mut transfer = curl.transfer();
+            transfer.header_function(|header| {
+                if let Ok(header) = std::str::from_utf8(header) {
+                    if let Some((name, value)) = header.split_once(':') {
+                        let name = name.trim();
+                        if name.eq_ignore_ascii_case("location") {
+                            location = Some(value.trim().to_string());
+                        } else if name.eq_ignore_ascii_case("retry-after") {
+                            retry_after = parse_retry_after(value, SystemTime::now());
+                        }
+                    }
+                }
+                true
+            })?;
+            // Abort the transfer on receiving response body since only headers are necessary
+            transfer.write_function(|_| Ok(0))?;
+            match transfer.perform() {
+                Err(err) if get && err.is_write_error() => {}
+                ret => ret?,
+            }
+        }
+        let status = curl.response_code()?;
+        // libcurl does not compute the redirect URL when the transfer was aborted
+        let next = match curl.redirect_url()? {
+            Some(u) => Some(u.to_string()),
+            None if (300..400).contains(&status) => location.as_ref().map(|l| join_url(url, l)),
+            None => None,
         };
-        let red = resolved.and_then(|u| {
-            (u != url).then(|| {
-                if let Some(fragment) = fragment {
-                    format!("{}#{}", u, fragment)
-                } else {
-                    u.to_string()
+        let hop = Hop {
+            url: url.to_string(),
+            status,
+            location,
+        };
+        Ok(Response {
+            hop,
+            next,
+            retry_after,
+        })
+    }
+
+    fn follow_redirects(&self, url: &str) -> Resolution {
+        // https://datatracker.ietf.org/doc/html/rfc3986#section-3
+        let fragment = url.find('#').map(|i| &url[i + 1..]);
+
+        let mut current = url.to_string();
+        let mut resolved = None;
+        let mut chain = vec![];
+        loop {
+            let Response { hop, next, .. } = match self.send_with_retry(&current) {
+                Ok(res) => res,
+                Err(err) => {
+                    let kind = NetworkErrorKind::from_curl_error(&err);
+                    let outcome = Outcome::NetworkError(kind, err.to_string());
+                    return Resolution { outcome, chain };
                 }
-            })
-        });
-        debug!("Resolved redirect: {} -> {:?}", url, red);
-        self.cache.insert(url.to_string(), red.clone());
-        Ok(red)
+            };
+            let status = hop.status;
+            chain.push(hop);
+            if status >= 400 {
+                debug!("HTTP error while resolving {}: status {}", current, status);
+                let outcome = Outcome::HttpError(status);
+                return Resolution { outcome, chain };
+            }
+            let next = match next {
+                Some(u) => u,
+                None => break,
+            };
+            if !self.allowed_status.contains(&status) {
+                debug!(
+                    "Stopped following redirect {} -> {} due to status {}",
+                    current, next, status
+                );
+                break;
+            }
+            debug!("Redirect with status {}: {} -> {}", status, current, next);
+
+            if chain.len() > MAX_REDIRECTS {
+                let msg = format!("Too many redirects (more than {})", MAX_REDIRECTS);
+                let outcome = Outcome::NetworkError(NetworkErrorKind::TooManyRedirects, msg);
+                return Resolution { outcome, chain };
+            }
+            resolved = Some(next.clone());
+            current = next;
+            if self.shallow {
+                break; // Follow the first redirect only
+            }
+        }
+
+        let outcome = match resolved {
+            Some(u) if u != url => match fragment {
+                Some(fragment) if !u.contains('#') => {
+                    Outcome::Redirected(format!("{}#{}", u, fragment))
+                }
+                _ => Outcome::Redirected(u),
+            },
+            _ => Outcome::NotRedirected,
+        };
+        Resolution { outcome, chain }
     }
 }
 
@@ -58,91 +708,607 @@ impl Resolver for CurlResolver {
         self.shallow = enabled;
     }
 
-    fn resolve(&self, url: &str) -> Option<String> {
-        // Do not return error on resolving URLs because it is normal case that broken URL is passed to this function.
-        match self.try_resolve(url) {
-            Ok(ret) => ret,
-            Err(err) => {
-                warn!("Could not resolve {:?}: {}", url, err);
-                None
+    fn resolve(&self, url: &str) -> Resolution {
+        debug!("Resolving {}", url);
+
+        if let Some(r) = self.cache.get(url) {
+            debug!("Cache hit: {} -> {}", url, r.outcome);
+            return r.clone();
+        }
+
+        let policy = self.disk_cache.as_ref().map(|_| self.policy());
+        if let (Some(c), Some(p)) = (&self.disk_cache, &policy) {
+            if let Some(r) = c.get(url, p) {
+                debug!("Cache file hit: {} -> {}", url, r.outcome);
+                self.cache.insert(url.to_string(), r.clone());
+                return r;
             }
         }
+
+        let resolution = self.follow_redirects(url);
+        debug!(
+            "Resolved {}: {} (chain: {})",
+            url,
+            resolution.outcome,
+            resolution.chain_display(),
+        );
+        // Network errors and temporary HTTP errors are not cached since they may be resolved later
+        let temporary = match resolution.outcome {
+            Outcome::NetworkError(..) => true,
+            Outcome::HttpError(status) => RETRYABLE_STATUS.contains(&status),
+            _ => false,
+        };
+        if !temporary {
+            self.cache.insert(url.to_string(), resolution.clone());
+            if let (Some(c), Some(p)) = (&self.disk_cache, &policy) {
+                c.insert(url, p, &resolution);
+            }
+        }
+        resolution
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_helper::TestDir;
+    use crate::test_server::{Reply, TestCerts, TestServer};
+
+    // Redirect: /rhysd/vim-crystal -(301)-> /vim-crystal/vim-crystal -(302)-> /raw/README.md
+    fn vim_crystal_server() -> TestServer {
+        let server = TestServer::start();
+        server
+            .route(
+                "/rhysd/vim-crystal/raw/master/README.md",
+                Reply::redirect(301, "/vim-crystal/vim-crystal/raw/master/README.md"),
+            )
+            .route(
+                "/vim-crystal/vim-crystal/raw/master/README.md",
+                Reply::redirect(302, "/raw/README.md"),
+            )
+            .route("/raw/README.md", Reply::ok());
+        server
+    }
 
     #[test]
     fn resolve_url_with_cache() {
-        // Redirect: github.com/rhysd/ -> github.com/vim-crystal/ -> raw.githubusercontent
-        let url = "https://github.com/rhysd/vim-crystal/raw/master/README.md";
+        let server = vim_crystal_server();
+        let url = server.url("/rhysd/vim-crystal/raw/master/README.md");
 
-        let res = CurlResolver::default();
-        let resolved = res.try_resolve(url).unwrap();
-        let resolved = resolved.unwrap();
-        assert!(
-            resolved.starts_with("https://raw.githubusercontent.com/vim-crystal/"),
-            "URL: {}",
-            resolved
+        let res = CurlResolver::default().allow_status(vec![301, 302, 303, 307, 308]);
+        let resolution = res.resolve(&url);
+        assert_eq!(
+            resolution.redirect(),
+            Some(server.url("/raw/README.md").as_str())
+        );
+
+        let statuses: Vec<_> = resolution.chain.iter().map(|h| h.status).collect();
+        assert_eq!(statuses, &[301, 302, 200]);
+        assert_eq!(resolution.chain[0].url, url);
+        assert_eq!(
+            resolution.chain[1].location.as_deref(),
+            Some("/raw/README.md")
         );
+        assert_eq!(resolution.chain[2].location, None);
 
-        assert_eq!(*res.cache.get(url).unwrap(), Some(resolved.clone()));
+        assert_eq!(*res.cache.get(&url).unwrap(), resolution);
 
-        let cached = res.try_resolve(url).unwrap();
-        assert_eq!(resolved, cached.unwrap());
+        let cached = res.resolve(&url);
+        assert_eq!(resolution, cached);
+        assert_eq!(server.requests().len(), 3);
     }
 
     #[test]
     fn resolve_shallow_redirect() {
-        // Redirect: github.com/rhysd/ -> github.com/vim-crystal/ -> raw.githubusercontent
-        let url = "https://github.com/rhysd/vim-crystal/raw/master/README.md";
+        let server = vim_crystal_server();
+        let url = server.url("/rhysd/vim-crystal/raw/master/README.md");
 
-        let mut res = CurlResolver::default();
+        let mut res = CurlResolver::default().allow_status(vec![301, 302]);
         res.shallow(true);
-        let resolved = res.try_resolve(url).unwrap();
-        let resolved = resolved.unwrap();
-        assert!(
-            resolved.starts_with("https://github.com/vim-crystal/vim-crystal/"),
-            "URL: {}",
-            resolved
+        let resolution = res.resolve(&url);
+        assert_eq!(
+            resolution.redirect(),
+            Some(
+                server
+                    .url("/vim-crystal/vim-crystal/raw/master/README.md")
+                    .as_str()
+            )
+        );
+        assert_eq!(resolution.chain.len(), 1);
+    }
+
+    #[test]
+    fn resolve_permanent_redirect_only() {
+        let server = vim_crystal_server();
+        let url = server.url("/rhysd/vim-crystal/raw/master/README.md");
+
+        let res = CurlResolver::default();
+        let resolution = res.resolve(&url);
+        assert_eq!(
+            resolution.redirect(),
+            Some(
+                server
+                    .url("/vim-crystal/vim-crystal/raw/master/README.md")
+                    .as_str()
+            )
         );
+        let statuses: Vec<_> = resolution.chain.iter().map(|h| h.status).collect();
+        assert_eq!(statuses, &[301, 302]);
     }
 
     #[test]
     fn resolve_url_not_found() {
-        // Redirect: github.com/rhysd/ -> github.com/vim-crystal/ -> raw.githubusercontent
-        let url = "https://github.com/rhysd/this-repo-does-not-exist";
+        let server = TestServer::start();
+        let url = server.url("/rhysd/this-repo-does-not-exist");
 
         let res = CurlResolver::default();
-        let resolved = res.resolve(url);
-        assert_eq!(resolved, None);
+        let resolution = res.resolve(&url);
+        assert_eq!(resolution.outcome, Outcome::HttpError(404));
+        assert!(resolution.outcome.is_broken());
 
-        assert_eq!(*res.cache.get(url).unwrap(), None);
+        assert_eq!(*res.cache.get(&url).unwrap(), resolution);
 
-        let cached = res.resolve(url);
-        assert_eq!(resolved, cached);
+        let cached = res.resolve(&url);
+        assert_eq!(resolution, cached);
     }
 
     #[test]
     fn resolve_url_with_fragment() {
-        // Redirect: github.com/rhysd/ -> github.com/vim-crystal
-        let url = "https://github.com/rhysd/vim-crystal#readme";
+        let server = TestServer::start();
+        server
+            .redirects(301, &["/rhysd/vim-crystal", "/vim-crystal/vim-crystal"])
+            .route("/foo", Reply::redirect(301, "/bar#usage"))
+            .route("/bar", Reply::ok());
+
+        let res = CurlResolver::default();
+        let tests = &[
+            (
+                "/rhysd/vim-crystal#readme",
+                "/vim-crystal/vim-crystal#readme",
+            ),
+            // Fragment in Location header wins
+            ("/foo#install", "/bar#usage"),
+        ];
+        for (path, want) in tests {
+            let resolution = res.resolve(&server.url(path));
+            assert_eq!(
+                resolution.redirect(),
+                Some(server.url(want).as_str()),
+                "{:?}",
+                path
+            );
+        }
+        // Fragments are not sent to the server
+        assert!(
+            server.requests().iter().all(|r| !r.contains('#')),
+            "{:?}",
+            server.requests()
+        );
+    }
+
+    #[test]
+    fn resolve_head_rejected_url_with_get() {
+        let server = TestServer::start();
+        server
+            .route_method("HEAD", "/old", Reply::status(405))
+            .route("/old", Reply::redirect(301, "/new").body("moved"))
+            .route("/new", Reply::ok().body("hello"));
+        let url = server.url("/old");
+        let want = Outcome::Redirected(server.url("/new"));
+
+        let res = CurlResolver::default();
+        assert_eq!(res.resolve(&url).outcome, want);
+        assert_eq!(server.requests(), &["HEAD /old", "GET /old", "HEAD /new"],);
+
+        let res = CurlResolver::default().method(Method::Head);
+        assert_eq!(res.resolve(&url).outcome, Outcome::HttpError(405));
+
+        let res = CurlResolver::default().method(Method::Get);
+        assert_eq!(res.resolve(&url).outcome, want);
+    }
+
+    #[test]
+    fn retry_on_temporary_failure() {
+        let server = TestServer::start();
+        server
+            .route_seq(
+                "/old",
+                vec![
+                    Reply::status(503).header("Retry-After", "0"),
+                    Reply::status(429),
+                    Reply::redirect(301, "/new"),
+                ],
+            )
+            .route("/new", Reply::ok());
+        let url = server.url("/old");
+
+        let res = CurlResolver::default().retry_backoff(Duration::from_millis(1));
+        assert_eq!(
+            res.resolve(&url).outcome,
+            Outcome::Redirected(server.url("/new"))
+        );
+        let count = server
+            .requests()
+            .iter()
+            .filter(|r| r.ends_with("/old"))
+            .count();
+        assert_eq!(count, 3);
+
+        let server = TestServer::start();
+        server.route("/unavailable", Reply::status(503));
+        let res = CurlResolver::default()
+            .retries(1)
+            .retry_backoff(Duration::from_millis(1));
+        let url = server.url("/unavailable");
+        assert_eq!(res.resolve(&url).outcome, Outcome::HttpError(503));
+        assert_eq!(server.requests().len(), 2);
+        // Temporary failures are not cached
+        assert!(res.cache.get(&url).is_none());
+    }
+
+    #[test]
+    fn resolve_url_timeout() {
+        let server = TestServer::start();
+        server.route("/slow", Reply::ok().delay(Duration::from_secs(1)));
+
+        let res = CurlResolver::default()
+            .timeout(Duration::from_millis(100))
+            .retries(0);
+        let resolution = res.resolve(&server.url("/slow"));
+        assert!(
+            matches!(
+                resolution.outcome,
+                Outcome::NetworkError(NetworkErrorKind::Timeout, _)
+            ),
+            "{:?}",
+            resolution
+        );
+    }
+
+    #[test]
+    fn resolve_too_many_redirects() {
+        let server = TestServer::start();
+        server.route("/loop", Reply::redirect(301, "/loop"));
+
+        let res = CurlResolver::default();
+        let resolution = res.resolve(&server.url("/loop"));
+        assert!(
+            matches!(
+                resolution.outcome,
+                Outcome::NetworkError(NetworkErrorKind::TooManyRedirects, _)
+            ),
+            "{:?}",
+            resolution
+        );
+        assert_eq!(resolution.chain.len(), MAX_REDIRECTS + 1);
+    }
+
+    #[test]
+    fn follow_permanent_redirects_by_default() {
+        let res = CurlResolver::default();
+        assert_eq!(res.allowed_status, &[301, 308]);
+        let res = res.allow_status(vec![302]);
+        assert_eq!(res.allowed_status, &[302]);
+    }
+
+    #[test]
+    fn parse_method_policy() {
+        assert_eq!("head".parse(), Ok(Method::Head));
+        assert_eq!("get".parse(), Ok(Method::Get));
+        assert_eq!("head-then-get".parse(), Ok(Method::HeadThenGet));
+        assert!("post".parse::<Method>().is_err());
+
+        let res = CurlResolver::default();
+        assert_eq!(res.method, Method::HeadThenGet);
+        let res = res.method(Method::Get);
+        assert_eq!(res.method, Method::Get);
+    }
+
+    #[test]
+    fn retry_after_header() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(784111777); // Sun, 06 Nov 1994 08:49:37 GMT
+        let tests = &[
+            ("120", Some(Duration::from_secs(120))),
+            (" 0 ", Some(Duration::from_secs(0))),
+            (
+                "Sun, 06 Nov 1994 08:50:07 GMT",
+                Some(Duration::from_secs(30)),
+            ),
+            (
+                "Sun, 06 Nov 1994 08:00:00 GMT",
+                Some(Duration::from_secs(0)),
+            ),
+            ("-1", None),
+            ("soon", None),
+        ];
+        for (value, want) in tests {
+            assert_eq!(parse_retry_after(value, now), *want, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn delay_of_retry() {
+        let secs = Duration::from_secs;
+        let tests = &[
+            (None, secs(1), secs(1)),
+            (Some(secs(10)), secs(1), secs(10)),
+            (Some(secs(0)), secs(1), secs(1)),
+            // Too long Retry-After is capped instead of giving up retrying
+            (Some(secs(3600)), secs(1), MAX_RETRY_AFTER),
+        ];
+        for (retry_after, backoff, want) in tests {
+            assert_eq!(
+                retry_delay(*retry_after, *backoff),
+                *want,
+                "{:?}",
+                retry_after
+            );
+        }
+    }
 
+    #[test]
+    fn exponential_backoff_with_jitter() {
+        let base = Duration::from_millis(100);
+        for retry in 0..4 {
+            let max = base * 2u32.pow(retry);
+            for _ in 0..10 {
+                let d = backoff_delay(base, retry);
+                assert!(max / 2 <= d && d < max, "{:?} for retry {}", d, retry);
+            }
+        }
+        assert_eq!(
+            backoff_delay(Duration::from_secs(0), 3),
+            Duration::from_secs(0)
+        );
+        // Large retry count or base delay does not overflow
+        assert!(backoff_delay(base, u32::MAX) <= MAX_RETRY_AFTER);
+        let d = backoff_delay(Duration::from_secs(u64::MAX), 16);
+        assert!(MAX_RETRY_AFTER / 2 <= d && d <= MAX_RETRY_AFTER, "{:?}", d);
+    }
+
+    #[test]
+    fn retry_options() {
         let res = CurlResolver::default();
-        let resolved = res.resolve(url).unwrap();
+        assert_eq!(res.connect_timeout, DEFAULT_CONNECT_TIMEOUT);
+        assert_eq!(res.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(res.retries, DEFAULT_RETRIES);
+        assert_eq!(res.retry_backoff, DEFAULT_RETRY_BACKOFF);
+
+        let res = res
+            .connect_timeout(Duration::from_secs(1))
+            .timeout(Duration::from_secs(2))
+            .retries(0)
+            .retry_backoff(Duration::from_millis(10));
+        assert_eq!(res.connect_timeout, Duration::from_secs(1));
+        assert_eq!(res.timeout, Duration::from_secs(2));
+        assert_eq!(res.retries, 0);
+        assert_eq!(res.retry_backoff, Duration::from_millis(10));
+
+        // Too large base delay is clamped
+        let res = res.retry_backoff(Duration::from_millis(u64::MAX));
+        assert_eq!(res.retry_backoff, MAX_RETRY_AFTER);
+    }
+
+    #[test]
+    fn parse_tls_version() {
+        assert_eq!("1.2".parse::<TlsVersion>().unwrap(), TlsVersion::V1_2);
+        assert_eq!("1.3".parse::<TlsVersion>().unwrap(), TlsVersion::V1_3);
+        for input in &["", "1", "1.4", "tls1.2"] {
+            assert!(input.parse::<TlsVersion>().is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn resolve_url_with_custom_ca() {
+        let certs = TestCerts::new();
+        let server = TestServer::start_tls(certs.acceptor(false, None));
+        server.redirects(301, &["/old", "/new"]);
+        let url = server.url("/old");
+        let want = Outcome::Redirected(server.url("/new"));
+
+        // The CA is not trusted by the system
+        let res = CurlResolver::default().retries(0);
         assert!(
-            resolved.ends_with("/vim-crystal#readme"),
-            "URL: {}",
-            resolved
+            matches!(
+                res.resolve(&url).outcome,
+                Outcome::NetworkError(NetworkErrorKind::Tls, _)
+            ),
+            "{:?}",
+            res.resolve(&url)
+        );
+
+        let res = CurlResolver::default().ca_cert(&certs.ca_path);
+        assert_eq!(res.resolve(&url).outcome, want);
+
+        let res = CurlResolver::default().insecure(true);
+        assert_eq!(res.resolve(&url).outcome, want);
+    }
+
+    #[test]
+    fn resolve_url_with_client_cert() {
+        let certs = TestCerts::new();
+        let server = TestServer::start_tls(certs.acceptor(true, None));
+        server.redirects(301, &["/old", "/new"]);
+        let url = server.url("/old");
+
+        let res = CurlResolver::default().ca_cert(&certs.ca_path).retries(0);
+        assert!(res.resolve(&url).outcome.is_broken());
+
+        let res = CurlResolver::default()
+            .ca_cert(&certs.ca_path)
+            .client_cert(&certs.client_cert_path, Some(certs.client_key_path.clone()));
+        assert_eq!(
+            res.resolve(&url).outcome,
+            Outcome::Redirected(server.url("/new"))
         );
     }
 
+    #[test]
+    fn resolve_url_with_min_tls_version() {
+        let certs = TestCerts::new();
+        let acceptor = certs.acceptor(false, Some(openssl::ssl::SslVersion::TLS1_2));
+        let server = TestServer::start_tls(acceptor);
+        server.redirects(301, &["/old", "/new"]);
+        let url = server.url("/old");
+
+        let res = CurlResolver::default()
+            .ca_cert(&certs.ca_path)
+            .min_tls_version(TlsVersion::V1_2);
+        assert_eq!(
+            res.resolve(&url).outcome,
+            Outcome::Redirected(server.url("/new"))
+        );
+
+        let res = CurlResolver::default()
+            .ca_cert(&certs.ca_path)
+            .min_tls_version(TlsVersion::V1_3)
+            .retries(0);
+        assert!(
+            matches!(
+                res.resolve(&url).outcome,
+                Outcome::NetworkError(NetworkErrorKind::Tls, _)
+            ),
+            "{:?}",
+            res.resolve(&url)
+        );
+    }
+
+    #[test]
+    fn resolve_url_via_proxy() {
+        let proxy = TestServer::start();
+        proxy.redirects(301, &["/old", "/new"]);
+        // .invalid TLD never resolves so the request succeeds only via the proxy
+        let url = "http://fixred.invalid/old";
+
+        let res = CurlResolver::default().proxy(proxy.url(""));
+        assert_eq!(
+            res.resolve(url).outcome,
+            Outcome::Redirected("http://fixred.invalid/new".to_string())
+        );
+        assert_eq!(
+            proxy.requests(),
+            &[
+                "HEAD http://fixred.invalid/old",
+                "HEAD http://fixred.invalid/new"
+            ]
+        );
+
+        let res = CurlResolver::default()
+            .proxy(proxy.url(""))
+            .no_proxy("fixred.invalid")
+            .retries(0);
+        assert!(res.resolve(url).outcome.is_broken());
+        assert_eq!(proxy.requests().len(), 2);
+    }
+
+    #[test]
+    fn resolve_url_with_disk_cache() {
+        let dir = TestDir::new(&[]).unwrap();
+        let cache = Arc::new(DiskCache::open(dir.root.join("cache.json")).unwrap());
+        // Nothing listens on port 1 so this URL is resolved only via the cache
+        let url = "http://127.0.0.1:1/foo";
+        let cached = Resolution::new(Outcome::Redirected("http://127.0.0.1:1/bar".to_string()));
+        let policy = CurlResolver::default().policy();
+        cache.insert(url, &policy, &cached);
+
+        let res = CurlResolver::default().retries(0).disk_cache(cache.clone());
+        assert_eq!(res.resolve(url), cached);
+        assert_eq!(*res.cache.get(url).unwrap(), cached);
+
+        let url = "http://127.0.0.1:1/piyo";
+        let resolution = res.resolve(url);
+        assert!(resolution.outcome.is_broken(), "{:?}", resolution);
+        assert_eq!(cache.get(url, &policy), None);
+    }
+
+    #[test]
+    fn do_not_reuse_disk_cache_with_other_options() {
+        let dir = TestDir::new(&[]).unwrap();
+        let cache = Arc::new(DiskCache::open(dir.root.join("cache.json")).unwrap());
+        let url = "http://127.0.0.1:1/foo";
+        let cached = Resolution::new(Outcome::Redirected("http://127.0.0.1:1/bar".to_string()));
+
+        let mut res = CurlResolver::default()
+            .allow_status(vec![301, 302])
+            .disk_cache(cache.clone());
+        res.shallow(true);
+        cache.insert(url, &res.policy(), &cached);
+        assert_eq!(res.resolve(url), cached);
+
+        // Resolution with other options is not used. Nothing listens on port 1 so resolving the URL fails
+        let res = CurlResolver::default().retries(0).disk_cache(cache.clone());
+        let resolution = res.resolve(url);
+        assert!(resolution.outcome.is_broken(), "{:?}", resolution);
+    }
+
+    #[test]
+    fn display_redirect_chain() {
+        let r = Resolution {
+            outcome: Outcome::Redirected("https://b.example.com".to_string()),
+            chain: vec![
+                Hop {
+                    url: "https://a.example.com".to_string(),
+                    status: 301,
+                    location: Some("https://b.example.com".to_string()),
+                },
+                Hop {
+                    url: "https://b.example.com".to_string(),
+                    status: 200,
+                    location: None,
+                },
+            ],
+        };
+        assert_eq!(
+            r.chain_display().to_string(),
+            "https://a.example.com (301) -> https://b.example.com (200)"
+        );
+        let r = Resolution::new(Outcome::Skipped);
+        assert_eq!(r.chain_display().to_string(), "");
+    }
+
+    #[test]
+    fn broken_outcomes() {
+        let tests = &[
+            (
+                Outcome::Redirected("https://example.com".to_string()),
+                false,
+            ),
+            (Outcome::NotRedirected, false),
+            (Outcome::HttpError(404), true),
+            (
+                Outcome::NetworkError(NetworkErrorKind::Dns, "oops".to_string()),
+                true,
+            ),
+            (Outcome::Skipped, false),
+        ];
+        for (outcome, want) in tests {
+            assert_eq!(outcome.is_broken(), *want, "{:?}", outcome);
+        }
+    }
+
+    #[test]
+    fn outcome_kind_is_serialized_tag() {
+        for outcome in [
+            Outcome::Redirected("https://example.com".to_string()),
+            Outcome::NotRedirected,
+            Outcome::HttpError(404),
+            Outcome::NetworkError(NetworkErrorKind::Dns, "oops".to_string()),
+            Outcome::Skipped,
+        ] {
+            let v = serde_json::to_value(Resolution::new(outcome.clone())).unwrap();
+            assert_eq!(v["outcome"], outcome.kind(), "{:?}", outcome);
+        }
+    }
+
     #[test]
     fn url_parse_error() {
         let res = CurlResolver::default();
-        let resolved = res.try_resolve("https://");
-        assert!(resolved.is_err(), "{:?}", resolved);
+        let resolution = res.resolve("https://");
+        assert!(
+            matches!(resolution.outcome, Outcome::NetworkError(..)),
+            "{:?}",
+            resolution
+        );
+        assert!(res.cache.get("https://").is_none());
     }
 }
@@ -0,0 +1,230 @@
+use crate::resolve::{Outcome, Resolution, Resolver};
+use anyhow::{Context, Result};
+use log::debug;
+use regex::Regex;
+
+// Rule to rewrite URLs matched to the pattern. The replacement can refer capture groups like $1 or ${name}
+#[derive(Debug)]
+pub struct Rule {
+    pub pattern: Regex,
+    pub replace: String,
+}
+
+impl Rule {
+    pub fn new(pattern: &str, replace: impl Into<String>) -> Result<Self> {
+        let pattern = Regex::new(pattern)
+            .with_context(|| format!("Invalid pattern {:?} in rule", pattern))?;
+        Ok(Self {
+            pattern,
+            replace: replace.into(),
+        })
+    }
+
+    fn apply(&self, url: &str) -> Option<String> {
+        if !self.pattern.is_match(url) {
+            return None;
+        }
+        Some(
+            self.pattern
+                .replace(url, self.replace.as_str())
+                .into_owned(),
+        )
+    }
+}
+
+// Resolver to rewrite URLs with rules without sending any request. Rules are applied in order and each rule rewrites
+// the URL rewritten by previous rules like sed script. When shallow is set, only the first matched rule is applied
+#[derive(Default, Debug)]
+pub struct RuleResolver {
+    shallow: bool,
+    rules: Vec<Rule>,
+}
+
+impl RuleResolver {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        debug!("Rules to rewrite URLs: {:?}", rules);
+        Self {
+            shallow: false,
+            rules,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl Resolver for RuleResolver {
+    fn shallow(&mut self, enabled: bool) {
+        self.shallow = enabled;
+    }
+
+    fn resolve(&self, url: &str) -> Resolution {
+        let mut current = url.to_string();
+        for rule in self.rules.iter() {
+            if let Some(rewritten) = rule.apply(&current) {
+                debug!(
+                    "Rewrote {} -> {} with rule {:?}",
+                    current, rewritten, rule.pattern
+                );
+                current = rewritten;
+                if self.shallow {
+                    break;
+                }
+            }
+        }
+        let outcome = if current != url {
+            Outcome::Redirected(current)
+        } else {
+            Outcome::NotRedirected
+        };
+        Resolution::new(outcome)
+    }
+}
+
+// Resolver which rewrites URLs with the rules and then resolves the rewritten URLs with the inner resolver, since
+// rewritten URLs may still be redirected. URLs which no rule matched are resolved with the inner resolver as they are.
+// When shallow is set, rewritten URLs are not resolved further
+pub struct Rewrite<R: Resolver> {
+    rules: RuleResolver,
+    inner: R,
+}
+
+impl<R: Resolver> Rewrite<R> {
+    pub fn new(rules: RuleResolver, inner: R) -> Self {
+        Self { rules, inner }
+    }
+}
+
+impl<R: Resolver> Resolver for Rewrite<R> {
+    fn shallow(&mut self, enabled: bool) {
+        self.rules.shallow(enabled);
+        self.inner.shallow(enabled);
+    }
+
+    fn resolve(&self, url: &str) -> Resolution {
+        let rewritten = self.rules.resolve(url);
+        let to = match rewritten.redirect() {
+            Some(to) if !self.rules.shallow => to,
+            Some(_) => return rewritten,
+            None => return self.inner.resolve(url),
+        };
+        let res = self.inner.resolve(to);
+        match res.outcome {
+            // Errors of the rewritten URL are reported so that broken URLs are not written
+            Outcome::Redirected(_) | Outcome::HttpError(_) | Outcome::NetworkError(..) => res,
+            Outcome::NotRedirected | Outcome::Skipped => Resolution {
+                outcome: rewritten.outcome,
+                chain: res.chain,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::CurlResolver;
+    use crate::test_helper::FooToPiyoResolver;
+    use crate::test_server::{Reply, TestServer};
+
+    fn rules() -> RuleResolver {
+        RuleResolver::new(vec![
+            Rule::new(r"^http://(www\.)?example\.com/", "https://example.com/").unwrap(),
+            Rule::new(
+                r"^https://travis-ci\.org/(.+)$",
+                "https://app.travis-ci.com/$1",
+            )
+            .unwrap(),
+            Rule::new(
+                r"^https://example\.com/docs/(?P<page>[^#]+)",
+                "https://docs.example.com/v2/${page}",
+            )
+            .unwrap(),
+        ])
+    }
+
+    #[test]
+    fn rewrite_urls_with_rules() {
+        let res = rules();
+        let tests = &[
+            ("http://example.com/foo", Some("https://example.com/foo")),
+            (
+                "http://www.example.com/foo",
+                Some("https://example.com/foo"),
+            ),
+            (
+                "https://travis-ci.org/rhysd/fixred",
+                Some("https://app.travis-ci.com/rhysd/fixred"),
+            ),
+            (
+                "http://example.com/docs/intro#usage",
+                Some("https://docs.example.com/v2/intro#usage"),
+            ),
+            ("https://example.com/foo", None),
+            ("https://github.com/rhysd/fixred", None),
+        ];
+        for (url, want) in tests {
+            let res = res.resolve(url);
+            assert_eq!(res.redirect(), *want, "{:?}", url);
+            if want.is_none() {
+                assert_eq!(res.outcome, Outcome::NotRedirected, "{:?}", url);
+            }
+            assert!(res.chain.is_empty());
+        }
+    }
+
+    #[test]
+    fn apply_first_rule_only_on_shallow() {
+        let mut res = rules();
+        res.shallow(true);
+        assert_eq!(
+            res.resolve("http://example.com/docs/intro").redirect(),
+            Some("https://example.com/docs/intro")
+        );
+    }
+
+    #[test]
+    fn resolve_rewritten_urls() {
+        let server = TestServer::start();
+        server
+            .route("/moved", Reply::redirect(301, "/final"))
+            .route("/final", Reply::ok())
+            .route("/ok", Reply::ok())
+            .route("/gone", Reply::status(404));
+        let rules = || {
+            RuleResolver::new(vec![
+                Rule::new("/old$", "/moved").unwrap(),
+                Rule::new("/old-ok$", "/ok").unwrap(),
+                Rule::new("/old-gone$", "/gone").unwrap(),
+            ])
+        };
+        let mut res = Rewrite::new(rules(), CurlResolver::default().retries(0));
+
+        // The rewritten URL is still redirected
+        let r = res.resolve(&server.url("/old"));
+        assert_eq!(r.outcome, Outcome::Redirected(server.url("/final")));
+        assert_eq!(r.chain.len(), 2);
+        let r = res.resolve(&server.url("/old-ok"));
+        assert_eq!(r.outcome, Outcome::Redirected(server.url("/ok")));
+        let r = res.resolve(&server.url("/old-gone"));
+        assert_eq!(r.outcome, Outcome::HttpError(404));
+        // No rule matched
+        let r = res.resolve(&server.url("/moved"));
+        assert_eq!(r.outcome, Outcome::Redirected(server.url("/final")));
+
+        res.shallow(true);
+        let r = res.resolve(&server.url("/old"));
+        assert_eq!(r.outcome, Outcome::Redirected(server.url("/moved")));
+
+        let res = Rewrite::new(rules(), FooToPiyoResolver::default());
+        let r = res.resolve("https://foo.example.com/old");
+        assert_eq!(r.redirect(), Some("https://piyo.example.com/moved"));
+    }
+
+    #[test]
+    fn invalid_rule_pattern() {
+        let err = Rule::new("(foo", "bar").unwrap_err();
+        assert!(format!("{}", err).contains("(foo"), "{}", err);
+    }
+}
@@ -0,0 +1,466 @@
+use crate::url::find_all_urls;
+use std::borrow::Cow;
+use std::path::Path;
+use std::str::FromStr;
+
+// Which parts of source files are searched for URLs
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SourceMode {
+    // Whole file is searched as plain text
+    #[default]
+    Text,
+    // Only comments including doc comments
+    Comments,
+    // Comments and string literals
+    CommentsAndStrings,
+}
+
+impl FromStr for SourceMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "comments" => Ok(Self::Comments),
+            "strings" => Ok(Self::CommentsAndStrings),
+            _ => Err(format!(
+                "Unknown source mode {:?}. It must be one of \"text\", \"comments\", \"strings\"",
+                s
+            )),
+        }
+    }
+}
+
+struct StringLit {
+    open: &'static str,
+    close: &'static str,
+    // Backslash escapes the next character
+    escape: bool,
+}
+
+const fn lit(open: &'static str, close: &'static str, escape: bool) -> StringLit {
+    StringLit {
+        open,
+        close,
+        escape,
+    }
+}
+
+// Lexical syntax of comments and string literals. Literals are tried in order so longer delimiters must come first
+struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    nested_block_comment: bool,
+    strings: &'static [StringLit],
+    // Character literals like 'a' which must be distinguished from lifetimes like 'a in Rust
+    char_literals: bool,
+    // Rust raw strings like r#"..."# with any number of #
+    raw_strings: bool,
+    // Triple-quoted strings in statement position are docstrings, which are treated as comments in Python
+    docstrings: bool,
+}
+
+const RUST: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    nested_block_comment: true,
+    strings: &[lit("\"", "\"", true)],
+    char_literals: true,
+    raw_strings: true,
+    docstrings: false,
+};
+
+const GO: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    nested_block_comment: false,
+    strings: &[
+        lit("`", "`", false),
+        lit("\"", "\"", true),
+        lit("'", "'", true),
+    ],
+    char_literals: false,
+    raw_strings: false,
+    docstrings: false,
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    nested_block_comment: false,
+    strings: &[
+        lit("`", "`", true),
+        lit("\"", "\"", true),
+        lit("'", "'", true),
+    ],
+    char_literals: false,
+    raw_strings: false,
+    docstrings: false,
+};
+
+const C: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    nested_block_comment: false,
+    strings: &[
+        lit("\"\"\"", "\"\"\"", true), // Text blocks in Java, Kotlin, Scala and Swift
+        lit("\"", "\"", true),
+        lit("'", "'", true),
+    ],
+    char_literals: false,
+    raw_strings: false,
+    docstrings: false,
+};
+
+const PYTHON: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comment: None,
+    nested_block_comment: false,
+    strings: &[
+        lit("\"\"\"", "\"\"\"", true),
+        lit("'''", "'''", true),
+        lit("\"", "\"", true),
+        lit("'", "'", true),
+    ],
+    char_literals: false,
+    raw_strings: false,
+    docstrings: true,
+};
+
+const RUBY: Syntax = Syntax {
+    line_comments: &["#"],
+    block_comment: Some(("=begin", "=end")),
+    nested_block_comment: false,
+    strings: &[lit("\"", "\"", true), lit("'", "'", true)],
+    char_literals: false,
+    raw_strings: false,
+    docstrings: false,
+};
+
+// Programming languages whose comments and string literals are recognized
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Language {
+    Rust,
+    Go,
+    // JavaScript and TypeScript
+    JavaScript,
+    // C, C++, Java, Kotlin, Scala, Swift and C#
+    C,
+    Python,
+    Ruby,
+}
+
+impl Language {
+    // Detect the language from the file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        let lang = match ext.as_str() {
+            "rs" => Self::Rust,
+            "go" => Self::Go,
+            "js" | "mjs" | "cjs" | "jsx" | "ts" | "mts" | "cts" | "tsx" => Self::JavaScript,
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" | "java" | "kt" | "kts"
+            | "scala" | "swift" | "cs" => Self::C,
+            "py" | "pyi" => Self::Python,
+            "rb" => Self::Ruby,
+            _ => return None,
+        };
+        Some(lang)
+    }
+
+    fn syntax(self) -> &'static Syntax {
+        match self {
+            Self::Rust => &RUST,
+            Self::Go => &GO,
+            Self::JavaScript => &JAVASCRIPT,
+            Self::C => &C,
+            Self::Python => &PYTHON,
+            Self::Ruby => &RUBY,
+        }
+    }
+}
+
+fn char_len(s: &str, i: usize) -> usize {
+    s[i..].chars().next().map(char::len_utf8).unwrap_or(1)
+}
+
+// Length of Rust character literal at the start of s. None when it is a lifetime like 'a
+fn rust_char_literal(s: &str) -> Option<usize> {
+    let after = &s[1..];
+    if after.starts_with('\\') {
+        // Skip the escaped character since it may be '
+        let from = if after.len() > 1 {
+            1 + char_len(after, 1)
+        } else {
+            1
+        };
+        let end = after[from..].find('\'')?;
+        return Some(1 + from + end + 1);
+    }
+    let c = after.chars().next()?;
+    after[c.len_utf8()..]
+        .starts_with('\'')
+        .then(|| 1 + c.len_utf8() + 1)
+}
+
+// Number of # of Rust raw string like r##"..."## at the start of s
+fn rust_raw_string(s: &str) -> Option<usize> {
+    let hashes = s
+        .strip_prefix('r')?
+        .bytes()
+        .take_while(|&b| b == b'#')
+        .count();
+    (s.as_bytes().get(1 + hashes) == Some(&b'"')).then_some(hashes)
+}
+
+// Whether only indentation precedes the position in its line
+fn at_line_start(content: &str, i: usize) -> bool {
+    content[..i]
+        .rsplit('\n')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .is_empty()
+}
+
+// Find ranges of comments (and string literals when include_strings is true) in source code. Delimiters like // and
+// quotes are not included in the ranges
+fn find_regions(content: &str, syntax: &Syntax, include_strings: bool) -> Vec<(usize, usize)> {
+    let len = content.len();
+    let mut regions = vec![];
+    let mut i = 0;
+    while i < len {
+        let rest = &content[i..];
+
+        if let Some(p) = syntax.line_comments.iter().find(|p| rest.starts_with(*p)) {
+            let end = rest.find('\n').map(|n| i + n).unwrap_or(len);
+            regions.push((i + p.len(), end));
+            i = end;
+            continue;
+        }
+
+        if let Some((open, close)) = syntax.block_comment {
+            if rest.starts_with(open) {
+                let start = i + open.len();
+                let mut depth = 1;
+                let mut j = start;
+                while j < len {
+                    let rest = &content[j..];
+                    if syntax.nested_block_comment && rest.starts_with(open) {
+                        depth += 1;
+                        j += open.len();
+                    } else if rest.starts_with(close) {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                        j += close.len();
+                    } else {
+                        j += char_len(content, j);
+                    }
+                }
+                regions.push((start, j));
+                i = (j + close.len()).min(len);
+                continue;
+            }
+        }
+
+        if syntax.char_literals && rest.starts_with('\'') {
+            i += rust_char_literal(rest).unwrap_or(1).min(rest.len());
+            continue;
+        }
+
+        let string = match syntax.raw_strings.then(|| rust_raw_string(rest)).flatten() {
+            Some(hashes) => Some((
+                2 + hashes,
+                Cow::Owned(format!("\"{}", "#".repeat(hashes))),
+                false,
+            )),
+            None => syntax
+                .strings
+                .iter()
+                .find(|s| rest.starts_with(s.open))
+                .map(|s| (s.open.len(), Cow::Borrowed(s.close), s.escape)),
+        };
+        if let Some((open_len, close, escape)) = string {
+            let docstring = syntax.docstrings && open_len == 3 && at_line_start(content, i);
+            let start = i + open_len;
+            let mut j = start;
+            while j < len {
+                let rest = &content[j..];
+                if escape && rest.starts_with('\\') {
+                    j += 1;
+                    if j < len {
+                        j += char_len(content, j);
+                    }
+                } else if rest.starts_with(close.as_ref()) {
+                    break;
+                } else {
+                    j += char_len(content, j);
+                }
+            }
+            let end = j.min(len);
+            if include_strings || docstring {
+                regions.push((start, end));
+            }
+            i = (end + close.len()).min(len);
+            continue;
+        }
+
+        i += char_len(content, i);
+    }
+    regions
+}
+
+// Find URLs in comments of source code written in the language. URLs in string literals are also found when
+// include_strings is true
+pub fn find_source_urls(
+    content: &str,
+    lang: Language,
+    include_strings: bool,
+) -> Vec<(usize, usize)> {
+    find_regions(content, lang.syntax(), include_strings)
+        .into_iter()
+        .flat_map(|(start, end)| {
+            find_all_urls(&content[start..end])
+                .into_iter()
+                .map(move |(s, e)| (start + s, start + e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_urls(content: &str, lang: Language, include_strings: bool) -> Vec<&str> {
+        find_source_urls(content, lang, include_strings)
+            .into_iter()
+            .map(|(s, e)| &content[s..e])
+            .collect()
+    }
+
+    #[test]
+    fn find_urls_in_comments() {
+        let tests: &[(Language, &str, &[&str], &[&str])] = &[
+            (
+                Language::Rust,
+                r#####"
+//! See https://example.com/crate
+/// Doc https://example.com/doc
+fn f<'a>(s: &'a str) -> char {
+    let url = "https://example.com/api"; // https://example.com/line
+    let raw = r#"https://example.com/raw "quoted" "#;
+    let raw4 = r####"https://example.com/raw4 "### // not comment"####;
+    /* https://example.com/block /* nested */ https://example.com/after */
+    let q = '"'; // https://example.com/quote
+    let e = '\''; // https://example.com/escaped
+    'x'
+}
+"#####,
+                &[
+                    "https://example.com/crate",
+                    "https://example.com/doc",
+                    "https://example.com/line",
+                    "https://example.com/block",
+                    "https://example.com/after",
+                    "https://example.com/quote",
+                    "https://example.com/escaped",
+                ],
+                &[
+                    "https://example.com/api",
+                    "https://example.com/raw",
+                    "https://example.com/raw4",
+                ],
+            ),
+            (
+                Language::Go,
+                "// Package foo https://example.com/pkg\nvar a = \"https://example.com/a\\\"\" + `https://example.com/b`\nvar r = '\"' /* https://example.com/c */\n",
+                &["https://example.com/pkg", "https://example.com/c"],
+                &["https://example.com/a", "https://example.com/b"],
+            ),
+            (
+                Language::JavaScript,
+                "const a = 'https://example.com/a // not comment';\nconst b = `${x} https://example.com/b`; /** @see https://example.com/c */\n",
+                &["https://example.com/c"],
+                &["https://example.com/a", "https://example.com/b"],
+            ),
+            (
+                Language::C,
+                "#include <stdio.h> // https://example.com/a\nchar *s = \"https://example.com/b /* not comment */\";\nchar c = '\"'; /* https://example.com/c\n * https://example.com/d */\n",
+                &[
+                    "https://example.com/a",
+                    "https://example.com/c",
+                    "https://example.com/d",
+                ],
+                &["https://example.com/b"],
+            ),
+            (
+                Language::Python,
+                "\"\"\"Docstring https://example.com/doc\"\"\"\ndef f():\n    '''https://example.com/f'''\n    s = \"\"\"https://example.com/s\"\"\"\nurl = 'https://example.com/a#frag'  # https://example.com/b\n",
+                &[
+                    "https://example.com/doc",
+                    "https://example.com/f",
+                    "https://example.com/b",
+                ],
+                &["https://example.com/s", "https://example.com/a#frag"],
+            ),
+            (
+                Language::Ruby,
+                "url = \"https://example.com/a#frag\" # https://example.com/b\n=begin\nhttps://example.com/c\n=end\n",
+                &["https://example.com/b", "https://example.com/c"],
+                &["https://example.com/a#frag"],
+            ),
+        ];
+        for (lang, input, comments, strings) in tests {
+            assert_eq!(&find_urls(input, *lang, false), comments, "{:?}", lang);
+            let mut want: Vec<_> = comments.iter().chain(strings.iter()).copied().collect();
+            want.sort_by_key(|u| input.find(u).unwrap());
+            assert_eq!(find_urls(input, *lang, true), want, "{:?}", lang);
+        }
+    }
+
+    #[test]
+    fn unterminated_literals() {
+        for input in &[
+            "/* https://example.com/a",
+            "\"https://example.com/a",
+            "'\\",
+            "'\\あ",
+            "\"\\",
+            "'",
+        ] {
+            find_source_urls(input, Language::Rust, true);
+            find_source_urls(input, Language::C, true);
+        }
+        assert_eq!(
+            find_urls("/* https://example.com/a", Language::Rust, false),
+            &["https://example.com/a"]
+        );
+    }
+
+    #[test]
+    fn detect_language() {
+        let tests = &[
+            ("main.rs", Some(Language::Rust)),
+            ("main.go", Some(Language::Go)),
+            ("index.TS", Some(Language::JavaScript)),
+            ("App.tsx", Some(Language::JavaScript)),
+            ("App.jsx", Some(Language::JavaScript)),
+            ("Main.java", Some(Language::C)),
+            ("setup.py", Some(Language::Python)),
+            ("README.md", None),
+            ("Makefile", None),
+        ];
+        for (path, want) in tests {
+            assert_eq!(Language::from_path(Path::new(path)), *want, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn source_mode() {
+        assert_eq!("text".parse(), Ok(SourceMode::Text));
+        assert_eq!("comments".parse(), Ok(SourceMode::Comments));
+        assert_eq!("strings".parse(), Ok(SourceMode::CommentsAndStrings));
+        assert!("code".parse::<SourceMode>().is_err());
+    }
+}
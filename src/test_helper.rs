@@ -1,4 +1,4 @@
-use crate::resolve::Resolver;
+use crate::resolve::{Hop, NetworkErrorKind, Outcome, Resolution, Resolver};
 use std::env;
 use std::fs;
 use std::io::{Error, ErrorKind, Result, Write};
@@ -8,6 +8,7 @@ use std::time;
 
 pub(crate) struct WriteErrorWriter;
 impl Write for WriteErrorWriter {
+    #[allow(clippy::io_other_error)]
     fn write(&mut self, _buf: &[u8]) -> Result<usize> {
         Err(Error::new(ErrorKind::Other, "test"))
     }
@@ -21,6 +22,7 @@ impl Write for FlushErrorWriter {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         Ok(buf.len())
     }
+    #[allow(clippy::io_other_error)]
     fn flush(&mut self) -> Result<()> {
         Err(Error::new(ErrorKind::Other, "test"))
     }
@@ -116,7 +118,7 @@ pub(crate) fn assert_files(files: &[(PathBuf, String)]) {
     }
 }
 
-// redirect foo -> bar -> piyo
+// redirect foo -> bar -> piyo. URLs containing 'error' fail to be resolved and URLs containing 'notfound' are 404
 #[derive(Default)]
 pub(crate) struct FooToPiyoResolver {
     pub shallow: bool,
@@ -126,9 +128,44 @@ impl Resolver for FooToPiyoResolver {
     fn shallow(&mut self, b: bool) {
         self.shallow = b;
     }
-    fn resolve(&self, url: &str) -> Option<String> {
+    fn resolve(&self, url: &str) -> Resolution {
+        if url.contains("error") {
+            let msg = format!("could not resolve {}", url);
+            return Resolution::new(Outcome::NetworkError(NetworkErrorKind::Other, msg));
+        }
+        if url.contains("notfound") {
+            let chain = vec![Hop {
+                url: url.to_string(),
+                status: 404,
+                location: None,
+            }];
+            let outcome = Outcome::HttpError(404);
+            return Resolution { outcome, chain };
+        }
         let to = if self.shallow { "bar" } else { "piyo" };
         let new = url.replace("foo", to);
-        (url != new).then(|| new)
+        if url == new {
+            let chain = vec![Hop {
+                url: url.to_string(),
+                status: 200,
+                location: None,
+            }];
+            let outcome = Outcome::NotRedirected;
+            return Resolution { outcome, chain };
+        }
+        let chain = vec![
+            Hop {
+                url: url.to_string(),
+                status: 301,
+                location: Some(new.clone()),
+            },
+            Hop {
+                url: new.clone(),
+                status: 200,
+                location: None,
+            },
+        ];
+        let outcome = Outcome::Redirected(new);
+        Resolution { outcome, chain }
     }
 }
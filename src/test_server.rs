@@ -0,0 +1,351 @@
+use crate::test_helper::TestDir;
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::ssl::{SslAcceptor, SslMethod, SslVerifyMode, SslVersion};
+use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+use openssl::x509::{X509NameBuilder, X509};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Response of the test server scripted for a route
+#[derive(Clone, Debug)]
+pub(crate) struct Reply {
+    status: u32,
+    headers: Vec<(String, String)>,
+    body: String,
+    delay: Duration,
+}
+
+impl Reply {
+    pub fn status(status: u32) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: String::new(),
+            delay: Duration::from_secs(0),
+        }
+    }
+
+    pub fn ok() -> Self {
+        Self::status(200)
+    }
+
+    pub fn redirect(status: u32, location: &str) -> Self {
+        Self::status(status).header("Location", location)
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    // Body is sent only for GET requests
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+
+    // Wait before sending the response
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+struct Route {
+    // None matches all methods
+    method: Option<String>,
+    path: String,
+    // Replies are returned in order and the last one is repeated
+    replies: Vec<Reply>,
+    count: usize,
+}
+
+#[derive(Default)]
+struct State {
+    routes: Vec<Route>,
+    // "{method} {target}" of all received requests in order
+    requests: Vec<String>,
+}
+
+impl State {
+    fn reply(&mut self, method: &str, path: &str) -> Reply {
+        let route = self
+            .routes
+            .iter_mut()
+            .filter(|r| r.path == path && r.method.as_deref().map(|m| m == method).unwrap_or(true))
+            .min_by_key(|r| r.method.is_none()); // Route for the method is preferred
+        match route {
+            Some(route) => {
+                let idx = route.count.min(route.replies.len() - 1);
+                route.count += 1;
+                route.replies[idx].clone()
+            }
+            None => Reply::status(404),
+        }
+    }
+}
+
+// HTTP server running in the same process for testing. Responses are scripted per path with routes. Requests to
+// unknown paths are answered with status 404. Fragments are never sent to servers so routes do not have them
+pub(crate) struct TestServer {
+    addr: SocketAddr,
+    scheme: &'static str,
+    state: Arc<Mutex<State>>,
+}
+
+impl TestServer {
+    pub fn start() -> Self {
+        Self::spawn(None)
+    }
+
+    pub fn start_tls(acceptor: SslAcceptor) -> Self {
+        Self::spawn(Some(Arc::new(acceptor)))
+    }
+
+    fn spawn(acceptor: Option<Arc<SslAcceptor>>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let scheme = if acceptor.is_some() { "https" } else { "http" };
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = state.clone();
+        // The thread is never joined. It is stopped when the test process exits
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                let acceptor = acceptor.clone();
+                let state = shared.clone();
+                thread::spawn(move || match acceptor {
+                    Some(acceptor) => {
+                        if let Ok(stream) = acceptor.accept(stream) {
+                            handle(stream, &state);
+                        }
+                    }
+                    None => handle(stream, &state),
+                });
+            }
+        });
+        Self {
+            addr,
+            scheme,
+            state,
+        }
+    }
+
+    // Respond to requests to the path with the reply
+    pub fn route(&self, path: &str, reply: Reply) -> &Self {
+        self.add_route(None, path, vec![reply])
+    }
+
+    // Respond to requests with the method to the path with the reply. This wins over routes for all methods
+    pub fn route_method(&self, method: &str, path: &str, reply: Reply) -> &Self {
+        self.add_route(Some(method), path, vec![reply])
+    }
+
+    // Respond to requests to the path with the replies in order. The last reply is repeated
+    pub fn route_seq(&self, path: &str, replies: Vec<Reply>) -> &Self {
+        self.add_route(None, path, replies)
+    }
+
+    // Redirect paths in order with the status. The last path is answered with status 200
+    pub fn redirects(&self, status: u32, paths: &[&str]) -> &Self {
+        for w in paths.windows(2) {
+            self.route(w[0], Reply::redirect(status, w[1]));
+        }
+        if let Some(last) = paths.last() {
+            self.route(last, Reply::ok());
+        }
+        self
+    }
+
+    fn add_route(&self, method: Option<&str>, path: &str, replies: Vec<Reply>) -> &Self {
+        assert!(!replies.is_empty());
+        let route = Route {
+            method: method.map(str::to_string),
+            path: path.to_string(),
+            replies,
+            count: 0,
+        };
+        self.state.lock().unwrap().routes.push(route);
+        self
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}://{}{}", self.scheme, self.addr, path)
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+fn handle<S: Read + Write>(stream: S, state: &Mutex<State>) {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    // Skip request headers
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) if line == "\r\n" || line == "\n" => break,
+            Ok(_) => {}
+        }
+    }
+
+    let mut parts = request_line.split_ascii_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let target = parts.next().unwrap_or("/").to_string();
+    // Request target is an absolute URL when the server is used as proxy
+    let path = match target.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or("/"),
+        None => &target,
+    };
+
+    let reply = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(format!("{} {}", method, target));
+        state.reply(&method, path)
+    };
+    thread::sleep(reply.delay);
+
+    let mut response = format!("HTTP/1.1 {} Test\r\n", reply.status);
+    for (name, value) in reply.headers.iter() {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        reply.body.len()
+    ));
+    if method != "HEAD" {
+        response.push_str(&reply.body);
+    }
+    let stream = reader.get_mut();
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
+
+// Serial numbers must be unique among certificates issued by the same CA
+static CERT_SERIAL: AtomicU32 = AtomicU32::new(1);
+
+fn generate_key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+// Build a certificate signed by the issuer. The certificate is self-signed when the issuer is not given
+fn build_cert(cn: &str, key: &PKey<Private>, issuer: Option<(&X509, &PKey<Private>)>) -> X509 {
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", cn).unwrap();
+    let name = name.build();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    let serial = BigNum::from_u32(CERT_SERIAL.fetch_add(1, Ordering::Relaxed)).unwrap();
+    builder
+        .set_serial_number(&serial.to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+
+    match issuer {
+        Some((cert, _)) => {
+            builder.set_issuer_name(cert.subject_name()).unwrap();
+            let san = SubjectAlternativeName::new()
+                .dns("localhost")
+                .ip("127.0.0.1")
+                .build(&builder.x509v3_context(Some(cert), None))
+                .unwrap();
+            builder.append_extension(san).unwrap();
+        }
+        None => {
+            builder.set_issuer_name(&name).unwrap();
+            let ca = BasicConstraints::new().critical().ca().build().unwrap();
+            builder.append_extension(ca).unwrap();
+        }
+    }
+
+    let signer = issuer.map(|(_, k)| k).unwrap_or(key);
+    builder.sign(signer, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+// CA, server certificate and client certificate for testing TLS connections. The CA certificate and the client
+// certificate are written to the test directory as PEM files
+pub(crate) struct TestCerts {
+    pub ca_path: PathBuf,
+    pub client_cert_path: PathBuf,
+    pub client_key_path: PathBuf,
+    ca: X509,
+    server_cert: X509,
+    server_key: PKey<Private>,
+    _dir: TestDir,
+}
+
+impl TestCerts {
+    pub fn new() -> Self {
+        let dir = TestDir::new(&[]).unwrap();
+
+        let ca_key = generate_key();
+        let ca = build_cert("fixred test CA", &ca_key, None);
+        let server_key = generate_key();
+        let server_cert = build_cert("localhost", &server_key, Some((&ca, &ca_key)));
+        let client_key = generate_key();
+        let client_cert = build_cert("fixred test client", &client_key, Some((&ca, &ca_key)));
+
+        let ca_path = dir.root.join("ca.pem");
+        fs::write(&ca_path, ca.to_pem().unwrap()).unwrap();
+        let client_cert_path = dir.root.join("client.pem");
+        fs::write(&client_cert_path, client_cert.to_pem().unwrap()).unwrap();
+        let client_key_path = dir.root.join("client-key.pem");
+        fs::write(
+            &client_key_path,
+            client_key.private_key_to_pem_pkcs8().unwrap(),
+        )
+        .unwrap();
+
+        Self {
+            ca_path,
+            client_cert_path,
+            client_key_path,
+            ca,
+            server_cert,
+            server_key,
+            _dir: dir,
+        }
+    }
+
+    // Acceptor of TLS server. When client_auth is true, the server requires a client certificate signed by the CA
+    pub fn acceptor(&self, client_auth: bool, max_version: Option<SslVersion>) -> SslAcceptor {
+        let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        builder.set_private_key(&self.server_key).unwrap();
+        builder.set_certificate(&self.server_cert).unwrap();
+        builder.set_max_proto_version(max_version).unwrap();
+        if client_auth {
+            builder.cert_store_mut().add_cert(self.ca.clone()).unwrap();
+            builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        }
+        builder.build()
+    }
+}